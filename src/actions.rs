@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+/// What a key does.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    ZoomIn,
+    ZoomOut,
+    /// Fly back to the view the renderer started with.
+    ResetView,
+    SelectNext,
+    SelectPrevious,
+    /// Remove the selected entity.
+    Delete,
+    /// Revert the last edit of the scene.
+    Undo,
+    /// Apply the last undone edit again.
+    Redo,
+}
+
+impl Action {
+    const NAMES: [(&'static str, Action); 12] = [
+        ("panLeft", Action::PanLeft),
+        ("panRight", Action::PanRight),
+        ("panUp", Action::PanUp),
+        ("panDown", Action::PanDown),
+        ("zoomIn", Action::ZoomIn),
+        ("zoomOut", Action::ZoomOut),
+        ("resetView", Action::ResetView),
+        ("selectNext", Action::SelectNext),
+        ("selectPrevious", Action::SelectPrevious),
+        ("delete", Action::Delete),
+        ("undo", Action::Undo),
+        ("redo", Action::Redo),
+    ];
+
+    /// Parse the camelCase name used in the options, like `"panLeft"`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        Self::NAMES
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, action)| *action)
+    }
+
+    /// Pan actions last as long as their key is held, the others happen once per key press.
+    pub fn is_held(self) -> bool {
+        matches!(
+            self,
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown
+        )
+    }
+}
+
+/// Name of a key press in the bindings: the `KeyboardEvent.key` value, after `Ctrl+`, `Alt+`
+/// and `Shift+` when it comes with ctrl, cmd or alt, like `Ctrl+Shift+z`. Shift alone is left
+/// out, it already changes the key value.
+pub(crate) fn key_name(key: &str, ctrl: bool, alt: bool, shift: bool) -> String {
+    if !ctrl && !alt {
+        return key.to_string();
+    }
+    let mut name = String::new();
+    for (held, modifier) in [(ctrl, "Ctrl+"), (alt, "Alt+"), (shift, "Shift+")] {
+        if held {
+            name.push_str(modifier);
+        }
+    }
+    // shift turns `z` into `Z`
+    if key.chars().count() == 1 {
+        name.push_str(&key.to_lowercase());
+    } else {
+        name.push_str(key);
+    }
+    name
+}
+
+/// The key of a key name, without its modifiers.
+fn base_key(name: &str) -> &str {
+    let mut key = name;
+    while let Some(rest) = ["Ctrl+", "Alt+", "Shift+"]
+        .iter()
+        .find_map(|modifier| key.strip_prefix(modifier))
+    {
+        key = rest;
+    }
+    key
+}
+
+/// Actions by key name, see [`key_name`].
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Bindings {
+    keys: HashMap<String, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            ("ArrowLeft", Action::PanLeft),
+            ("ArrowRight", Action::PanRight),
+            ("ArrowUp", Action::PanUp),
+            ("ArrowDown", Action::PanDown),
+            ("+", Action::ZoomIn),
+            // `+` shares its key with `=` on most layouts
+            ("=", Action::ZoomIn),
+            ("-", Action::ZoomOut),
+            ("Home", Action::ResetView),
+            ("]", Action::SelectNext),
+            ("[", Action::SelectPrevious),
+            ("Delete", Action::Delete),
+            ("Backspace", Action::Delete),
+            ("Ctrl+z", Action::Undo),
+            ("Ctrl+Shift+z", Action::Redo),
+            ("Ctrl+y", Action::Redo),
+        ];
+        Bindings {
+            keys: keys
+                .iter()
+                .map(|(key, action)| (key.to_string(), *action))
+                .collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.keys.get(key).copied()
+    }
+
+    pub fn bind(&mut self, key: String, action: Action) {
+        self.keys.insert(key, action);
+    }
+
+    pub fn unbind(&mut self, key: &str) {
+        self.keys.remove(key);
+    }
+}
+
+/// Keyboard actions received since the last frame.
+#[derive(Default)]
+pub(crate) struct KeyboardInput {
+    /// Held actions with the key holding them.
+    held: Vec<(String, Action)>,
+    pressed: Vec<Action>,
+}
+
+impl KeyboardInput {
+    /// Returns whether the key is bound. Repeated presses only repeat the zoom and selection.
+    pub fn key_down(&mut self, bindings: &Bindings, key: &str, repeat: bool) -> bool {
+        let action = match bindings.action(key) {
+            Some(action) => action,
+            None => return false,
+        };
+        if action.is_held() {
+            if !self.held.iter().any(|(held, _)| held == key) {
+                self.held.push((key.to_string(), action));
+            }
+        } else if !repeat
+            || matches!(
+                action,
+                Action::ZoomIn | Action::ZoomOut | Action::SelectNext | Action::SelectPrevious
+            )
+        {
+            self.pressed.push(action);
+        }
+        true
+    }
+
+    /// `key` is the `KeyboardEvent.key` value, it releases the bindings with modifiers too.
+    pub fn key_up(&mut self, key: &str) {
+        self.held
+            .retain(|(held, _)| !base_key(held).eq_ignore_ascii_case(key));
+    }
+
+    /// The keys go up without telling when the page loses focus.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.iter().any(|(_, held)| *held == action)
+    }
+
+    pub fn is_panning(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    pub fn take_pressed(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.pressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate;
+    use crate::tests::setup;
+
+    #[test]
+    fn default_bindings_cover_arrows_zoom_and_home() {
+        let bindings = Bindings::default();
+
+        assert_eq!(bindings.action("ArrowUp"), Some(Action::PanUp));
+        assert_eq!(bindings.action("+"), Some(Action::ZoomIn));
+        assert_eq!(bindings.action("-"), Some(Action::ZoomOut));
+        assert_eq!(bindings.action("Home"), Some(Action::ResetView));
+        assert_eq!(bindings.action("a"), None);
+    }
+
+    #[test]
+    fn bindings_can_be_overridden() {
+        let mut bindings = Bindings::default();
+
+        bindings.bind("a".to_string(), Action::PanLeft);
+        bindings.bind("Home".to_string(), Action::SelectNext);
+        bindings.unbind("ArrowLeft");
+
+        assert_eq!(bindings.action("a"), Some(Action::PanLeft));
+        assert_eq!(bindings.action("Home"), Some(Action::SelectNext));
+        assert_eq!(bindings.action("ArrowLeft"), None);
+        assert_eq!(
+            Action::from_name("selectPrevious"),
+            Some(Action::SelectPrevious)
+        );
+        assert_eq!(Action::from_name("jump"), None);
+    }
+
+    #[test]
+    fn modifiers_prefix_the_key_name() {
+        let bindings = Bindings::default();
+
+        assert_eq!(key_name("z", true, false, false), "Ctrl+z");
+        assert_eq!(key_name("Z", true, false, true), "Ctrl+Shift+z");
+        assert_eq!(
+            key_name("ArrowLeft", false, true, true),
+            "Alt+Shift+ArrowLeft"
+        );
+        assert_eq!(key_name("+", false, false, true), "+");
+        assert_eq!(
+            bindings.action(&key_name("Z", true, false, true)),
+            Some(Action::Redo)
+        );
+    }
+
+    #[test]
+    fn pan_keys_are_held_until_released() {
+        let bindings = Bindings::default();
+        let mut input = KeyboardInput::default();
+
+        assert!(input.key_down(&bindings, "ArrowLeft", false));
+        assert!(input.key_down(&bindings, "ArrowLeft", true));
+        assert!(input.key_down(&bindings, "Delete", false));
+        assert!(input.key_down(&bindings, "Delete", true));
+        assert!(!input.key_down(&bindings, "q", false));
+
+        assert!(input.is_held(Action::PanLeft));
+        assert_eq!(input.take_pressed(), vec![Action::Delete]);
+        input.key_up("ArrowLeft");
+        assert!(!input.is_panning());
+
+        let mut bindings = bindings;
+        bindings.bind("Ctrl+Shift+a".to_string(), Action::PanLeft);
+        input.key_down(&bindings, &key_name("A", true, false, true), false);
+        assert!(input.is_held(Action::PanLeft));
+        input.key_up("a");
+        assert!(!input.is_panning());
+    }
+
+    #[test]
+    fn held_arrow_keys_pan_every_frame() {
+        let (_, mut renderer) = setup(|_| {});
+        let start = renderer.camera.target();
+
+        assert!(renderer.key_down("ArrowRight", false));
+        simulate(&mut renderer, 0.0);
+        simulate(&mut renderer, 100.0);
+        let moved = renderer.camera.target();
+        renderer.keyboard.key_up("ArrowRight");
+        simulate(&mut renderer, 200.0);
+
+        assert!(moved.x > start.x);
+        assert_eq!(moved.y, start.y);
+        assert_eq!(renderer.camera.target(), moved);
+    }
+}
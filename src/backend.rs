@@ -0,0 +1,193 @@
+pub mod recording;
+mod webgl;
+
+pub use recording::{Call, RecordingBackend};
+pub use webgl::WebGlBackend;
+
+use crate::error::RendererError;
+use nalgebra::Matrix4;
+
+/// Handle of a vertex or index buffer owned by a [`Backend`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BufferId(pub u32);
+
+/// Handle of a texture owned by a [`Backend`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TextureId(pub u32);
+
+/// Handle of a linked shader program owned by a [`Backend`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ProgramId(pub u32);
+
+/// Handle of a vertex array, the attribute and index bindings of a mesh for one program.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct VertexArrayId(pub u32);
+
+/// Handle of an off-screen RGBA target with a depth buffer, owned by a [`Backend`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FramebufferId(pub u32);
+
+/// Optional features of the underlying graphics API.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Capabilities {
+    pub webgl2: bool,
+    /// Vertex arrays are real GPU objects instead of being replayed at every bind.
+    pub vertex_array_objects: bool,
+    /// 32-bit index buffers can be created.
+    pub element_index_uint: bool,
+}
+
+/// Type of the components of a vertex attribute, as stored in the buffer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AttributeType {
+    Float,
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+}
+
+impl AttributeType {
+    /// Bytes taken by one component.
+    pub fn size(self) -> i32 {
+        match self {
+            AttributeType::Float => 4,
+            AttributeType::Byte | AttributeType::UnsignedByte => 1,
+            AttributeType::Short | AttributeType::UnsignedShort => 2,
+        }
+    }
+}
+
+/// Source of one vertex attribute of a vertex array.
+#[derive(Clone, PartialEq, Debug)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub buffer: BufferId,
+    /// Number of components per vertex, 1 to 4.
+    pub components: i32,
+    pub kind: AttributeType,
+    /// Integer components are mapped to 0..1, or -1..1 when signed, instead of kept as is.
+    pub normalized: bool,
+    /// Bytes from one vertex to the next, 0 when the attribute is tightly packed.
+    pub stride: i32,
+    /// Bytes from the start of the buffer to the first component.
+    pub offset: i32,
+}
+
+impl VertexAttribute {
+    /// Tightly packed floats, alone in `buffer`.
+    pub fn new(name: &str, buffer: BufferId, components: i32) -> VertexAttribute {
+        VertexAttribute {
+            name: name.to_string(),
+            buffer,
+            components,
+            kind: AttributeType::Float,
+            normalized: false,
+            stride: 0,
+            offset: 0,
+        }
+    }
+}
+
+/// What a backend did since [`Backend::take_frame_counters`] was last called.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct FrameCounters {
+    pub draw_calls: u32,
+    pub triangles: u32,
+    /// Program, texture and vertex array binds.
+    pub state_changes: u32,
+    /// Bytes used by the live textures, it is not reset.
+    pub texture_memory: u64,
+    /// Milliseconds the GPU spent on a recent frame, only measured with the
+    /// `EXT_disjoint_timer_query` extensions.
+    pub gpu_time: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum UniformValue {
+    Int(i32),
+    Vec4([f32; 4]),
+    Mat4(Matrix4<f32>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TextureSource {
+    /// Image fetched asynchronously, a magenta pixel is shown until it is loaded.
+    Url(String),
+    Rgba {
+        width: i32,
+        height: i32,
+        pixels: Vec<u8>,
+    },
+}
+
+/// Everything the engine needs from a graphics API.
+///
+/// Resources are referred to by plain ids so meshes and materials stay independent from the
+/// implementation, which lets the scene run against [`RecordingBackend`] in native tests.
+pub trait Backend {
+    /// Vertex data of any [`AttributeType`], laid out by the vertex arrays reading it.
+    fn create_vertex_buffer_bytes(&self, data: &[u8]) -> Result<BufferId, RendererError>;
+    fn create_index_buffer(&self, data: &[u16]) -> Result<BufferId, RendererError>;
+    /// Fails with [`RendererError::Unsupported`] unless [`Capabilities::element_index_uint`].
+    fn create_index_buffer_u32(&self, data: &[u32]) -> Result<BufferId, RendererError>;
+    fn delete_buffer(&self, buffer: BufferId);
+
+    /// Failures of asynchronous loads are reported to the backend error handler.
+    fn create_texture(&self, source: TextureSource) -> Result<TextureId, RendererError>;
+    fn delete_texture(&self, texture: TextureId);
+
+    fn create_program(
+        &self,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<ProgramId, RendererError>;
+    fn delete_program(&self, program: ProgramId);
+
+    /// Record how the attributes of `program` are fed and which index buffer is used.
+    /// Attributes the program does not use are skipped.
+    fn create_vertex_array(
+        &self,
+        program: ProgramId,
+        attributes: &[VertexAttribute],
+        indices: BufferId,
+    ) -> Result<VertexArrayId, RendererError>;
+    fn delete_vertex_array(&self, vertex_array: VertexArrayId);
+
+    fn create_framebuffer(&self, width: i32, height: i32) -> Result<FramebufferId, RendererError>;
+    fn delete_framebuffer(&self, framebuffer: FramebufferId);
+    /// Draw into `framebuffer`, or into the canvas with `None`.
+    fn bind_framebuffer(&self, framebuffer: Option<FramebufferId>);
+    /// RGBA bytes of one pixel of the bound framebuffer, `y` goes up from the bottom row.
+    fn read_pixel(&self, x: i32, y: i32) -> [u8; 4];
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// The context was lost, nothing is drawn and no resource can be created until it is
+    /// restored.
+    fn is_context_lost(&self) -> bool;
+    /// Re-create every live resource under its current id once the context is back, from the
+    /// descriptions they were created with.
+    fn restore(&self) -> Result<(), RendererError>;
+
+    /// Frame boundaries, the GPU time of what is drawn in between is measured when possible.
+    fn begin_frame(&self);
+    fn end_frame(&self);
+    fn take_frame_counters(&self) -> FrameCounters;
+
+    fn set_clear_color(&self, color: [f32; 4]);
+    fn viewport(&self, width: i32, height: i32);
+    fn clear(&self);
+
+    fn use_program(&self, program: ProgramId);
+    fn bind_texture(&self, unit: u32, texture: TextureId);
+    fn bind_vertex_array(&self, vertex_array: VertexArrayId) -> Result<(), RendererError>;
+    fn set_uniform(
+        &self,
+        program: ProgramId,
+        name: &str,
+        value: UniformValue,
+    ) -> Result<(), RendererError>;
+    /// Draw `index_count` indices of the bound vertex array as triangles.
+    fn draw_triangles(&self, index_count: i32);
+}
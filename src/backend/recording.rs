@@ -0,0 +1,379 @@
+use crate::backend::{
+    Backend, BufferId, Capabilities, FrameCounters, FramebufferId, ProgramId, TextureId,
+    TextureSource, UniformValue, VertexArrayId, VertexAttribute,
+};
+use crate::error::RendererError;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// One call received by a [`RecordingBackend`].
+#[derive(Clone, PartialEq, Debug)]
+pub enum Call {
+    CreateVertexBuffer(BufferId),
+    CreateIndexBuffer(BufferId),
+    DeleteBuffer(BufferId),
+    CreateTexture(TextureId),
+    DeleteTexture(TextureId),
+    CreateProgram(ProgramId),
+    DeleteProgram(ProgramId),
+    CreateVertexArray(VertexArrayId, ProgramId, Vec<VertexAttribute>, BufferId),
+    DeleteVertexArray(VertexArrayId),
+    CreateFramebuffer(FramebufferId, i32, i32),
+    DeleteFramebuffer(FramebufferId),
+    BindFramebuffer(Option<FramebufferId>),
+    ReadPixel(i32, i32),
+    SetClearColor([f32; 4]),
+    Viewport(i32, i32),
+    Clear,
+    UseProgram(ProgramId),
+    BindTexture(u32, TextureId),
+    BindVertexArray(VertexArrayId),
+    SetUniform(ProgramId, String, UniformValue),
+    DrawTriangles(i32),
+    Restore,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum BufferData {
+    VertexBytes(Vec<u8>),
+    Index(Vec<u16>),
+    IndexU32(Vec<u32>),
+}
+
+struct State {
+    capabilities: Capabilities,
+    next_id: u32,
+    calls: Vec<Call>,
+    buffers: HashMap<BufferId, BufferData>,
+    textures: HashMap<TextureId, TextureSource>,
+    vertex_arrays: HashMap<VertexArrayId, (Vec<VertexAttribute>, BufferId)>,
+    framebuffers: HashMap<FramebufferId, (i32, i32)>,
+    /// What `read_pixel` returns, nothing is really drawn.
+    pixel: [u8; 4],
+    /// Uniforms the shaders pretend not to have.
+    missing_uniforms: Vec<String>,
+    /// Attributes the shaders pretend not to use.
+    missing_attributes: Vec<String>,
+    context_lost: bool,
+    counters: FrameCounters,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            capabilities: Capabilities {
+                webgl2: true,
+                vertex_array_objects: true,
+                element_index_uint: true,
+            },
+            next_id: 0,
+            calls: vec![],
+            buffers: Default::default(),
+            textures: Default::default(),
+            vertex_arrays: Default::default(),
+            framebuffers: Default::default(),
+            pixel: [0; 4],
+            missing_uniforms: vec![],
+            missing_attributes: vec![],
+            context_lost: false,
+            counters: Default::default(),
+        }
+    }
+}
+
+impl State {
+    /// Like WebGL, creating anything fails while the context is lost.
+    fn next_id(&mut self, resource: &'static str) -> Result<u32, RendererError> {
+        if self.context_lost {
+            return Err(RendererError::ResourceCreation(resource));
+        }
+        self.next_id += 1;
+        Ok(self.next_id)
+    }
+}
+
+/// [`Backend`] that draws nothing and keeps a log of every call, it runs anywhere.
+///
+/// Clones share the same log, keep one around to inspect what a renderer did with the other.
+#[derive(Clone, Default)]
+pub struct RecordingBackend {
+    state: Rc<RefCell<State>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        Default::default()
+    }
+
+    /// Pretend to be a more limited API, by default every capability is available.
+    pub fn with_capabilities(capabilities: Capabilities) -> RecordingBackend {
+        let backend = RecordingBackend::new();
+        backend.state.borrow_mut().capabilities = capabilities;
+        backend
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.borrow().calls.clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state.borrow_mut().calls.clear();
+    }
+
+    /// Data uploaded to a buffer that was not deleted yet.
+    pub fn buffer_data(&self, buffer: BufferId) -> Option<BufferData> {
+        self.state.borrow().buffers.get(&buffer).cloned()
+    }
+
+    pub fn live_buffer_count(&self) -> usize {
+        self.state.borrow().buffers.len()
+    }
+
+    pub fn live_vertex_array_count(&self) -> usize {
+        self.state.borrow().vertex_arrays.len()
+    }
+
+    pub fn live_texture_count(&self) -> usize {
+        self.state.borrow().textures.len()
+    }
+
+    pub fn live_framebuffer_count(&self) -> usize {
+        self.state.borrow().framebuffers.len()
+    }
+
+    /// Make `read_pixel` return `pixel`, as if it had been drawn there.
+    pub fn set_pixel(&self, pixel: [u8; 4]) {
+        self.state.borrow_mut().pixel = pixel;
+    }
+
+    /// Make every program behave as if `name` was optimized out of its shaders.
+    pub fn remove_uniform(&self, name: &str) {
+        self.state
+            .borrow_mut()
+            .missing_uniforms
+            .push(name.to_string());
+    }
+
+    /// Make every program pretend not to use the attribute `name`, vertex arrays skip it.
+    pub fn remove_attribute(&self, name: &str) {
+        self.state
+            .borrow_mut()
+            .missing_attributes
+            .push(name.to_string());
+    }
+
+    /// Behave like a context dropped by the browser until [`Backend::restore`] is called.
+    pub fn lose_context(&self) {
+        self.state.borrow_mut().context_lost = true;
+    }
+
+    fn record_state_change(&self, call: Call) {
+        let mut state = self.state.borrow_mut();
+        state.counters.state_changes += 1;
+        state.calls.push(call);
+    }
+
+    fn record(&self, call: Call) {
+        self.state.borrow_mut().calls.push(call);
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn create_vertex_buffer_bytes(&self, data: &[u8]) -> Result<BufferId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = BufferId(state.next_id("buffer")?);
+        state
+            .buffers
+            .insert(id, BufferData::VertexBytes(data.to_vec()));
+        state.calls.push(Call::CreateVertexBuffer(id));
+        Ok(id)
+    }
+
+    fn create_index_buffer(&self, data: &[u16]) -> Result<BufferId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = BufferId(state.next_id("buffer")?);
+        state.buffers.insert(id, BufferData::Index(data.to_vec()));
+        state.calls.push(Call::CreateIndexBuffer(id));
+        Ok(id)
+    }
+
+    fn create_index_buffer_u32(&self, data: &[u32]) -> Result<BufferId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        if !state.capabilities.element_index_uint {
+            return Err(RendererError::Unsupported("32-bit indices"));
+        }
+        let id = BufferId(state.next_id("buffer")?);
+        state
+            .buffers
+            .insert(id, BufferData::IndexU32(data.to_vec()));
+        state.calls.push(Call::CreateIndexBuffer(id));
+        Ok(id)
+    }
+
+    fn delete_buffer(&self, buffer: BufferId) {
+        self.state.borrow_mut().buffers.remove(&buffer);
+        self.record(Call::DeleteBuffer(buffer));
+    }
+
+    fn create_texture(&self, source: TextureSource) -> Result<TextureId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = TextureId(state.next_id("texture")?);
+        state.textures.insert(id, source);
+        state.calls.push(Call::CreateTexture(id));
+        Ok(id)
+    }
+
+    fn delete_texture(&self, texture: TextureId) {
+        self.state.borrow_mut().textures.remove(&texture);
+        self.record(Call::DeleteTexture(texture));
+    }
+
+    fn create_program(
+        &self,
+        _vertex_source: &str,
+        _fragment_source: &str,
+    ) -> Result<ProgramId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = ProgramId(state.next_id("shader program")?);
+        state.calls.push(Call::CreateProgram(id));
+        Ok(id)
+    }
+
+    fn delete_program(&self, program: ProgramId) {
+        self.record(Call::DeleteProgram(program));
+    }
+
+    fn create_vertex_array(
+        &self,
+        program: ProgramId,
+        attributes: &[VertexAttribute],
+        indices: BufferId,
+    ) -> Result<VertexArrayId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = VertexArrayId(state.next_id("vertex array")?);
+        let attributes: Vec<_> = attributes
+            .iter()
+            .filter(|attribute| !state.missing_attributes.contains(&attribute.name))
+            .cloned()
+            .collect();
+        state
+            .vertex_arrays
+            .insert(id, (attributes.clone(), indices));
+        state
+            .calls
+            .push(Call::CreateVertexArray(id, program, attributes, indices));
+        Ok(id)
+    }
+
+    fn delete_vertex_array(&self, vertex_array: VertexArrayId) {
+        self.state.borrow_mut().vertex_arrays.remove(&vertex_array);
+        self.record(Call::DeleteVertexArray(vertex_array));
+    }
+
+    fn create_framebuffer(&self, width: i32, height: i32) -> Result<FramebufferId, RendererError> {
+        let mut state = self.state.borrow_mut();
+        let id = FramebufferId(state.next_id("framebuffer")?);
+        state.framebuffers.insert(id, (width, height));
+        state.calls.push(Call::CreateFramebuffer(id, width, height));
+        Ok(id)
+    }
+
+    fn delete_framebuffer(&self, framebuffer: FramebufferId) {
+        self.state.borrow_mut().framebuffers.remove(&framebuffer);
+        self.record(Call::DeleteFramebuffer(framebuffer));
+    }
+
+    fn bind_framebuffer(&self, framebuffer: Option<FramebufferId>) {
+        self.record(Call::BindFramebuffer(framebuffer));
+    }
+
+    fn read_pixel(&self, x: i32, y: i32) -> [u8; 4] {
+        self.record(Call::ReadPixel(x, y));
+        self.state.borrow().pixel
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.state.borrow().capabilities
+    }
+
+    fn is_context_lost(&self) -> bool {
+        self.state.borrow().context_lost
+    }
+
+    fn restore(&self) -> Result<(), RendererError> {
+        let mut state = self.state.borrow_mut();
+        state.context_lost = false;
+        state.calls.push(Call::Restore);
+        Ok(())
+    }
+
+    fn begin_frame(&self) {}
+
+    fn end_frame(&self) {}
+
+    fn take_frame_counters(&self) -> FrameCounters {
+        let mut state = self.state.borrow_mut();
+        let texture_memory = state
+            .textures
+            .values()
+            .map(|source| match source {
+                TextureSource::Url(_) => 4,
+                TextureSource::Rgba { width, height, .. } => *width as u64 * *height as u64 * 4,
+            })
+            .sum();
+        FrameCounters {
+            texture_memory,
+            ..std::mem::take(&mut state.counters)
+        }
+    }
+
+    fn set_clear_color(&self, color: [f32; 4]) {
+        self.record(Call::SetClearColor(color));
+    }
+
+    fn viewport(&self, width: i32, height: i32) {
+        self.record(Call::Viewport(width, height));
+    }
+
+    fn clear(&self) {
+        self.record(Call::Clear);
+    }
+
+    fn use_program(&self, program: ProgramId) {
+        self.record_state_change(Call::UseProgram(program));
+    }
+
+    fn bind_texture(&self, unit: u32, texture: TextureId) {
+        self.record_state_change(Call::BindTexture(unit, texture));
+    }
+
+    fn bind_vertex_array(&self, vertex_array: VertexArrayId) -> Result<(), RendererError> {
+        self.record_state_change(Call::BindVertexArray(vertex_array));
+        Ok(())
+    }
+
+    fn set_uniform(
+        &self,
+        program: ProgramId,
+        name: &str,
+        value: UniformValue,
+    ) -> Result<(), RendererError> {
+        let mut state = self.state.borrow_mut();
+        // uniform locations can't be queried on a lost context either
+        if state.context_lost || state.missing_uniforms.iter().any(|missing| missing == name) {
+            return Err(RendererError::MissingUniform(name.to_string()));
+        }
+        state
+            .calls
+            .push(Call::SetUniform(program, name.to_string(), value));
+        Ok(())
+    }
+
+    fn draw_triangles(&self, index_count: i32) {
+        let mut state = self.state.borrow_mut();
+        state.counters.draw_calls += 1;
+        state.counters.triangles += index_count as u32 / 3;
+        state.calls.push(Call::DrawTriangles(index_count));
+    }
+}
@@ -0,0 +1,1154 @@
+use crate::backend::{
+    AttributeType, Backend, BufferId, Capabilities, FrameCounters, FramebufferId, ProgramId,
+    TextureId, TextureSource, UniformValue, VertexArrayId, VertexAttribute,
+};
+use crate::error::{ErrorHandler, RendererError, ShaderStage};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    ExtDisjointTimerQuery, HtmlCanvasElement, HtmlImageElement, OesVertexArrayObject,
+    WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlQuery,
+    WebGlRenderbuffer, WebGlRenderingContext, WebGlShader, WebGlTexture, WebGlVertexArrayObject,
+};
+
+/// Run `$body` with `$gl` bound to the active context, WebGL2 has the WebGL1 API too.
+macro_rules! with_gl {
+    ($context:expr, $gl:ident => $body:expr) => {
+        match $context {
+            Context::WebGl1 { gl: $gl, .. } => $body,
+            Context::WebGl2($gl) => $body,
+        }
+    };
+}
+
+#[derive(Clone)]
+struct Extensions {
+    vertex_array_object: Option<OesVertexArrayObject>,
+    element_index_uint: bool,
+}
+
+#[derive(Clone)]
+enum Context {
+    WebGl1 {
+        gl: WebGlRenderingContext,
+        /// Extension objects die with the context, they are fetched again after a restore.
+        extensions: RefCell<Extensions>,
+    },
+    WebGl2(WebGl2RenderingContext),
+}
+
+/// CPU-side copy of the data of a buffer, uploaded again when the context is restored.
+enum BufferContents {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+struct Buffer {
+    buffer: WebGlBuffer,
+    target: u32,
+    usage: u32,
+    index_type: u32,
+    contents: BufferContents,
+}
+
+struct Texture {
+    texture: WebGlTexture,
+    source: TextureSource,
+    memory: Rc<Cell<u64>>,
+    /// Listeners of the image fetched for `texture`, dropping them ignores its outcome.
+    loading: Option<ImageLoad>,
+}
+
+struct ImageLoad {
+    _on_load: EventListener,
+    _on_error: EventListener,
+}
+
+struct Program {
+    program: WebGlProgram,
+    vertex_source: String,
+    fragment_source: String,
+}
+
+struct VertexArray {
+    /// `None` when vertex array objects are not supported, the bindings are replayed instead.
+    object: Option<WebGlVertexArrayObject>,
+    program: ProgramId,
+    attributes: Vec<VertexAttribute>,
+    indices: BufferId,
+}
+
+struct Framebuffer {
+    framebuffer: WebGlFramebuffer,
+    color: WebGlTexture,
+    depth: WebGlRenderbuffer,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Default)]
+struct Resources {
+    next_id: u32,
+    buffers: HashMap<u32, Buffer>,
+    textures: HashMap<u32, Texture>,
+    programs: HashMap<u32, Program>,
+    vertex_arrays: HashMap<u32, VertexArray>,
+    framebuffers: HashMap<u32, Framebuffer>,
+}
+
+impl Resources {
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// [`Backend`] drawing through WebGL2 when available, WebGL1 and its extensions otherwise.
+///
+/// Every resource keeps the description it was created from, so that [`Backend::restore`] can
+/// re-create all of them under the same ids once a lost context comes back.
+pub struct WebGlBackend {
+    context: Context,
+    error_handler: ErrorHandler,
+    on_texture_loaded: Rc<dyn Fn()>,
+    resources: RefCell<Resources>,
+    index_type: Cell<u32>,
+    /// Attribute arrays enabled on the default vertex array, used without vertex array objects.
+    enabled_attributes: RefCell<Vec<u32>>,
+    clear_color: Cell<[f32; 4]>,
+    counters: Cell<FrameCounters>,
+    gpu_timer: RefCell<Option<GpuTimer>>,
+}
+
+/// GPU time of frames with `EXT_disjoint_timer_query`, results come a few frames late.
+struct GpuTimer {
+    /// `None` on WebGL2, which has the query functions on the context.
+    extension: Option<ExtDisjointTimerQuery>,
+    pending: VecDeque<WebGlQuery>,
+    running: Option<WebGlQuery>,
+    last_result: Option<f64>,
+}
+
+impl GpuTimer {
+    const MAX_PENDING: usize = 4;
+
+    fn new(context: &Context) -> Option<GpuTimer> {
+        let extension = match context {
+            Context::WebGl1 { gl, .. } => Some(
+                gl.get_extension("EXT_disjoint_timer_query")
+                    .ok()??
+                    .unchecked_into::<ExtDisjointTimerQuery>(),
+            ),
+            Context::WebGl2(gl) => {
+                gl.get_extension("EXT_disjoint_timer_query_webgl2").ok()??;
+                None
+            }
+        };
+        Some(GpuTimer {
+            extension,
+            pending: VecDeque::new(),
+            running: None,
+            last_result: None,
+        })
+    }
+
+    fn begin(&mut self, context: &Context) {
+        self.collect(context);
+        if self.running.is_some() || self.pending.len() >= Self::MAX_PENDING {
+            return;
+        }
+        let query = match (&self.extension, context) {
+            (Some(extension), _) => extension.create_query_ext(),
+            (None, Context::WebGl2(gl)) => gl.create_query(),
+            (None, Context::WebGl1 { .. }) => None,
+        };
+        if let Some(query) = query {
+            match (&self.extension, context) {
+                (Some(extension), _) => {
+                    extension.begin_query_ext(ExtDisjointTimerQuery::TIME_ELAPSED_EXT, &query)
+                }
+                (None, Context::WebGl2(gl)) => {
+                    gl.begin_query(ExtDisjointTimerQuery::TIME_ELAPSED_EXT, &query)
+                }
+                (None, Context::WebGl1 { .. }) => {}
+            }
+            self.running = Some(query);
+        }
+    }
+
+    fn end(&mut self, context: &Context) {
+        if let Some(query) = self.running.take() {
+            match (&self.extension, context) {
+                (Some(extension), _) => {
+                    extension.end_query_ext(ExtDisjointTimerQuery::TIME_ELAPSED_EXT)
+                }
+                (None, Context::WebGl2(gl)) => {
+                    gl.end_query(ExtDisjointTimerQuery::TIME_ELAPSED_EXT)
+                }
+                (None, Context::WebGl1 { .. }) => {}
+            }
+            self.pending.push_back(query);
+        }
+    }
+
+    fn collect(&mut self, context: &Context) {
+        // a disjoint operation, like a GPU frequency change, invalidates the queries in flight
+        let disjoint = with_gl!(context, gl => gl
+            .get_parameter(ExtDisjointTimerQuery::GPU_DISJOINT_EXT)
+            .ok()
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false));
+        while let Some(query) = self.pending.front() {
+            let parameter = |name| match (&self.extension, context) {
+                (Some(extension), _) => extension.get_query_object_ext(query, name),
+                (None, Context::WebGl2(gl)) => gl.get_query_parameter(query, name),
+                (None, Context::WebGl1 { .. }) => JsValue::UNDEFINED,
+            };
+            if !disjoint && parameter(ExtDisjointTimerQuery::QUERY_RESULT_AVAILABLE_EXT) != true {
+                break;
+            }
+            if !disjoint {
+                let nanoseconds = parameter(ExtDisjointTimerQuery::QUERY_RESULT_EXT).as_f64();
+                self.last_result = nanoseconds.map(|nanoseconds| nanoseconds / 1_000_000.0);
+            }
+            let query = self.pending.pop_front().unwrap();
+            match (&self.extension, context) {
+                (Some(extension), _) => extension.delete_query_ext(Some(&query)),
+                (None, Context::WebGl2(gl)) => gl.delete_query(Some(&query)),
+                (None, Context::WebGl1 { .. }) => {}
+            }
+        }
+    }
+}
+
+impl WebGlBackend {
+    /// `error_handler` receives the failures of asynchronous texture loads and
+    /// `on_texture_loaded` is called when one of them completes.
+    pub fn new(
+        canvas: &HtmlCanvasElement,
+        prefer_webgl2: bool,
+        error_handler: ErrorHandler,
+        on_texture_loaded: Rc<dyn Fn()>,
+    ) -> Result<WebGlBackend, RendererError> {
+        let webgl2 = if prefer_webgl2 {
+            webgl2_context(canvas)
+        } else {
+            None
+        };
+        let context = match webgl2 {
+            Some(context) => context,
+            None => webgl1_context(canvas)?,
+        };
+
+        initialize_state(&context);
+        let gpu_timer = RefCell::new(GpuTimer::new(&context));
+
+        Ok(WebGlBackend {
+            context,
+            error_handler,
+            on_texture_loaded,
+            resources: Default::default(),
+            index_type: Cell::new(WebGlRenderingContext::UNSIGNED_SHORT),
+            enabled_attributes: Default::default(),
+            clear_color: Cell::new([0.0, 0.0, 0.0, 0.0]),
+            counters: Default::default(),
+            gpu_timer,
+        })
+    }
+
+    fn buffer(&self, buffer: BufferId) -> WebGlBuffer {
+        self.resources.borrow().buffers[&buffer.0].buffer.clone()
+    }
+
+    fn program(&self, program: ProgramId) -> WebGlProgram {
+        self.resources.borrow().programs[&program.0].program.clone()
+    }
+
+    fn create_buffer(
+        &self,
+        target: u32,
+        usage: u32,
+        index_type: u32,
+        contents: BufferContents,
+    ) -> Result<BufferId, RendererError> {
+        let buffer = upload_buffer(&self.context, target, usage, &contents)?;
+        let mut resources = self.resources.borrow_mut();
+        let id = resources.next_id();
+        resources.buffers.insert(
+            id,
+            Buffer {
+                buffer,
+                target,
+                usage,
+                index_type,
+                contents,
+            },
+        );
+        Ok(BufferId(id))
+    }
+
+    /// Recorded once into a vertex array object, or replayed at every bind without one.
+    fn apply_bindings(&self, vertex_array: &VertexArray) -> Result<(), RendererError> {
+        let program = self.program(vertex_array.program);
+        // interleaved attributes read the same buffer, it is bound once for all of them
+        let mut bound = None;
+        let mut used = Vec::new();
+        for attribute in &vertex_array.attributes {
+            let buffer = self.buffer(attribute.buffer);
+            let location = with_gl!(&self.context, gl => {
+                let location = gl.get_attrib_location(&program, &attribute.name);
+                // unused by the shader, or optimized away by the compiler
+                if location < 0 {
+                    None
+                } else {
+                    let location = location as u32;
+                    if bound != Some(attribute.buffer) {
+                        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&buffer));
+                        bound = Some(attribute.buffer);
+                    }
+                    gl.vertex_attrib_pointer_with_i32(
+                        location,
+                        attribute.components,
+                        attribute_type(attribute.kind),
+                        attribute.normalized,
+                        attribute.stride,
+                        attribute.offset,
+                    );
+                    gl.enable_vertex_attrib_array(location);
+                    Some(location)
+                }
+            });
+            if let Some(location) = location {
+                used.push(location);
+                if vertex_array.object.is_none() {
+                    let mut enabled = self.enabled_attributes.borrow_mut();
+                    if !enabled.contains(&location) {
+                        enabled.push(location);
+                    }
+                }
+            }
+        }
+        // the default vertex array keeps the arrays of the previous bindings enabled, reading
+        // them would go past the end of their buffers
+        if vertex_array.object.is_none() {
+            self.enabled_attributes.borrow_mut().retain(|&location| {
+                let keep = used.contains(&location);
+                if !keep {
+                    with_gl!(&self.context, gl => gl.disable_vertex_attrib_array(location));
+                }
+                keep
+            });
+        }
+        let indices = self.buffer(vertex_array.indices);
+        with_gl!(&self.context, gl => gl.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&indices),
+        ));
+        Ok(())
+    }
+
+    fn upload_texture(
+        &self,
+        source: &TextureSource,
+        memory: &Rc<Cell<u64>>,
+    ) -> Result<(WebGlTexture, Option<ImageLoad>), RendererError> {
+        match source {
+            TextureSource::Url(path) => load_texture(
+                &self.context,
+                path,
+                self.error_handler.clone(),
+                self.on_texture_loaded.clone(),
+                memory.clone(),
+            ),
+            TextureSource::Rgba {
+                width,
+                height,
+                pixels,
+            } => {
+                memory.set(texture_memory(*width as u32, *height as u32));
+                let texture = create_texture(&self.context, *width, *height, pixels)?;
+                Ok((texture, None))
+            }
+        }
+    }
+
+    fn count(&self, update: impl FnOnce(&mut FrameCounters)) {
+        let mut counters = self.counters.get();
+        update(&mut counters);
+        self.counters.set(counters);
+    }
+
+    fn build_vertex_array(
+        &self,
+        program: ProgramId,
+        attributes: Vec<VertexAttribute>,
+        indices: BufferId,
+    ) -> Result<VertexArray, RendererError> {
+        let object = match &self.context {
+            Context::WebGl2(gl) => gl.create_vertex_array(),
+            Context::WebGl1 { extensions, .. } => extensions
+                .borrow()
+                .vertex_array_object
+                .as_ref()
+                .and_then(|extension| extension.create_vertex_array_oes()),
+        };
+        let vertex_array = VertexArray {
+            object,
+            program,
+            attributes,
+            indices,
+        };
+
+        // record the bindings once, then restore the default vertex array so that later buffer
+        // binds do not end up in it. Without vertex array objects this only validates them.
+        self.bind_vertex_array_object(vertex_array.object.as_ref());
+        let result = self.apply_bindings(&vertex_array);
+        self.bind_vertex_array_object(None);
+        if let Err(error) = result {
+            if let Some(object) = &vertex_array.object {
+                self.delete_vertex_array_object(object);
+            }
+            return Err(error);
+        }
+        Ok(vertex_array)
+    }
+
+    fn delete_vertex_array_object(&self, object: &WebGlVertexArrayObject) {
+        match &self.context {
+            Context::WebGl2(gl) => gl.delete_vertex_array(Some(object)),
+            Context::WebGl1 { extensions, .. } => {
+                if let Some(extension) = &extensions.borrow().vertex_array_object {
+                    extension.delete_vertex_array_oes(Some(object));
+                }
+            }
+        }
+    }
+
+    fn bind_vertex_array_object(&self, object: Option<&WebGlVertexArrayObject>) {
+        match &self.context {
+            Context::WebGl2(gl) => gl.bind_vertex_array(object),
+            Context::WebGl1 { extensions, .. } => {
+                if let Some(extension) = &extensions.borrow().vertex_array_object {
+                    extension.bind_vertex_array_oes(object);
+                }
+            }
+        }
+    }
+}
+
+impl Backend for WebGlBackend {
+    fn create_vertex_buffer_bytes(&self, data: &[u8]) -> Result<BufferId, RendererError> {
+        self.create_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            WebGlRenderingContext::STATIC_DRAW,
+            0,
+            BufferContents::U8(data.to_vec()),
+        )
+    }
+
+    fn create_index_buffer(&self, data: &[u16]) -> Result<BufferId, RendererError> {
+        self.create_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            WebGlRenderingContext::STATIC_DRAW,
+            WebGlRenderingContext::UNSIGNED_SHORT,
+            BufferContents::U16(data.to_vec()),
+        )
+    }
+
+    fn create_index_buffer_u32(&self, data: &[u32]) -> Result<BufferId, RendererError> {
+        if !self.capabilities().element_index_uint {
+            return Err(RendererError::Unsupported("32-bit indices"));
+        }
+        self.create_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            WebGlRenderingContext::STATIC_DRAW,
+            WebGlRenderingContext::UNSIGNED_INT,
+            BufferContents::U32(data.to_vec()),
+        )
+    }
+
+    fn delete_buffer(&self, buffer: BufferId) {
+        if let Some(buffer) = self.resources.borrow_mut().buffers.remove(&buffer.0) {
+            with_gl!(&self.context, gl => gl.delete_buffer(Some(&buffer.buffer)));
+        }
+    }
+
+    fn create_texture(&self, source: TextureSource) -> Result<TextureId, RendererError> {
+        let memory = Rc::new(Cell::new(0));
+        let (texture, loading) = self.upload_texture(&source, &memory)?;
+        let mut resources = self.resources.borrow_mut();
+        let id = resources.next_id();
+        resources.textures.insert(
+            id,
+            Texture {
+                texture,
+                source,
+                memory,
+                loading,
+            },
+        );
+        Ok(TextureId(id))
+    }
+
+    fn delete_texture(&self, texture: TextureId) {
+        if let Some(texture) = self.resources.borrow_mut().textures.remove(&texture.0) {
+            with_gl!(&self.context, gl => gl.delete_texture(Some(&texture.texture)));
+        }
+    }
+
+    fn create_program(
+        &self,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<ProgramId, RendererError> {
+        let program = init_shader_program(&self.context, vertex_source, fragment_source)?;
+        let mut resources = self.resources.borrow_mut();
+        let id = resources.next_id();
+        resources.programs.insert(
+            id,
+            Program {
+                program,
+                vertex_source: vertex_source.to_string(),
+                fragment_source: fragment_source.to_string(),
+            },
+        );
+        Ok(ProgramId(id))
+    }
+
+    fn delete_program(&self, program: ProgramId) {
+        if let Some(program) = self.resources.borrow_mut().programs.remove(&program.0) {
+            with_gl!(&self.context, gl => gl.delete_program(Some(&program.program)));
+        }
+    }
+
+    fn create_vertex_array(
+        &self,
+        program: ProgramId,
+        attributes: &[VertexAttribute],
+        indices: BufferId,
+    ) -> Result<VertexArrayId, RendererError> {
+        let vertex_array = self.build_vertex_array(program, attributes.to_vec(), indices)?;
+        let mut resources = self.resources.borrow_mut();
+        let id = resources.next_id();
+        resources.vertex_arrays.insert(id, vertex_array);
+        Ok(VertexArrayId(id))
+    }
+
+    fn delete_vertex_array(&self, vertex_array: VertexArrayId) {
+        let vertex_array = self
+            .resources
+            .borrow_mut()
+            .vertex_arrays
+            .remove(&vertex_array.0);
+        if let Some(object) = vertex_array.and_then(|vertex_array| vertex_array.object) {
+            self.delete_vertex_array_object(&object);
+        }
+    }
+
+    fn create_framebuffer(&self, width: i32, height: i32) -> Result<FramebufferId, RendererError> {
+        let framebuffer = create_framebuffer(&self.context, width, height)?;
+        let mut resources = self.resources.borrow_mut();
+        let id = resources.next_id();
+        resources.framebuffers.insert(id, framebuffer);
+        Ok(FramebufferId(id))
+    }
+
+    fn delete_framebuffer(&self, framebuffer: FramebufferId) {
+        if let Some(framebuffer) = self
+            .resources
+            .borrow_mut()
+            .framebuffers
+            .remove(&framebuffer.0)
+        {
+            delete_framebuffer(&self.context, &framebuffer);
+        }
+    }
+
+    fn bind_framebuffer(&self, framebuffer: Option<FramebufferId>) {
+        let resources = self.resources.borrow();
+        let framebuffer = framebuffer
+            .and_then(|framebuffer| resources.framebuffers.get(&framebuffer.0))
+            .map(|framebuffer| &framebuffer.framebuffer);
+        with_gl!(&self.context, gl => gl.bind_framebuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            framebuffer,
+        ));
+    }
+
+    fn read_pixel(&self, x: i32, y: i32) -> [u8; 4] {
+        let mut pixel = [0; 4];
+        // reading outside of the framebuffer leaves the pixel transparent black
+        let _ = with_gl!(&self.context, gl => gl.read_pixels_with_opt_u8_array(
+            x,
+            y,
+            1,
+            1,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixel),
+        ));
+        pixel
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        match &self.context {
+            Context::WebGl2(_) => Capabilities {
+                webgl2: true,
+                vertex_array_objects: true,
+                element_index_uint: true,
+            },
+            Context::WebGl1 { extensions, .. } => {
+                let extensions = extensions.borrow();
+                Capabilities {
+                    webgl2: false,
+                    vertex_array_objects: extensions.vertex_array_object.is_some(),
+                    element_index_uint: extensions.element_index_uint,
+                }
+            }
+        }
+    }
+
+    fn is_context_lost(&self) -> bool {
+        with_gl!(&self.context, gl => gl.is_context_lost())
+    }
+
+    fn restore(&self) -> Result<(), RendererError> {
+        if let Context::WebGl1 { gl, extensions } = &self.context {
+            *extensions.borrow_mut() = webgl1_extensions(gl);
+        }
+        *self.gpu_timer.borrow_mut() = GpuTimer::new(&self.context);
+        initialize_state(&self.context);
+        // the new context starts with every attribute array disabled
+        self.enabled_attributes.borrow_mut().clear();
+        self.set_clear_color(self.clear_color.get());
+
+        {
+            let resources = &mut *self.resources.borrow_mut();
+            for buffer in resources.buffers.values_mut() {
+                buffer.buffer =
+                    upload_buffer(&self.context, buffer.target, buffer.usage, &buffer.contents)?;
+            }
+            for texture in resources.textures.values_mut() {
+                // replacing the listeners of a load still running drops its outcome
+                let (upload, loading) = self.upload_texture(&texture.source, &texture.memory)?;
+                texture.texture = upload;
+                texture.loading = loading;
+            }
+            for framebuffer in resources.framebuffers.values_mut() {
+                *framebuffer =
+                    create_framebuffer(&self.context, framebuffer.width, framebuffer.height)?;
+            }
+            for program in resources.programs.values_mut() {
+                program.program = init_shader_program(
+                    &self.context,
+                    &program.vertex_source,
+                    &program.fragment_source,
+                )?;
+            }
+        }
+
+        // vertex arrays read the programs and buffers restored above while recording
+        let vertex_arrays: Vec<_> = self
+            .resources
+            .borrow()
+            .vertex_arrays
+            .iter()
+            .map(|(id, vertex_array)| {
+                (
+                    *id,
+                    vertex_array.program,
+                    vertex_array.attributes.clone(),
+                    vertex_array.indices,
+                )
+            })
+            .collect();
+        for (id, program, attributes, indices) in vertex_arrays {
+            let vertex_array = self.build_vertex_array(program, attributes, indices)?;
+            self.resources
+                .borrow_mut()
+                .vertex_arrays
+                .insert(id, vertex_array);
+        }
+        Ok(())
+    }
+
+    fn begin_frame(&self) {
+        if let Some(gpu_timer) = &mut *self.gpu_timer.borrow_mut() {
+            gpu_timer.begin(&self.context);
+        }
+    }
+
+    fn end_frame(&self) {
+        if let Some(gpu_timer) = &mut *self.gpu_timer.borrow_mut() {
+            gpu_timer.end(&self.context);
+        }
+    }
+
+    fn take_frame_counters(&self) -> FrameCounters {
+        let texture_memory = self
+            .resources
+            .borrow()
+            .textures
+            .values()
+            .map(|texture| texture.memory.get())
+            .sum();
+        let gpu_time = self
+            .gpu_timer
+            .borrow()
+            .as_ref()
+            .and_then(|gpu_timer| gpu_timer.last_result);
+        FrameCounters {
+            texture_memory,
+            gpu_time,
+            ..self.counters.take()
+        }
+    }
+
+    fn set_clear_color(&self, color: [f32; 4]) {
+        self.clear_color.set(color);
+        let [r, g, b, a] = color;
+        with_gl!(&self.context, gl => gl.clear_color(r, g, b, a));
+    }
+
+    fn viewport(&self, width: i32, height: i32) {
+        with_gl!(&self.context, gl => gl.viewport(0, 0, width, height));
+    }
+
+    fn clear(&self) {
+        with_gl!(&self.context, gl => gl.clear(
+            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
+        ));
+    }
+
+    fn use_program(&self, program: ProgramId) {
+        self.count(|counters| counters.state_changes += 1);
+        let program = self.program(program);
+        with_gl!(&self.context, gl => gl.use_program(Some(&program)));
+    }
+
+    fn bind_texture(&self, unit: u32, texture: TextureId) {
+        self.count(|counters| counters.state_changes += 1);
+        let texture = self.resources.borrow().textures[&texture.0].texture.clone();
+        with_gl!(&self.context, gl => {
+            gl.active_texture(WebGlRenderingContext::TEXTURE0 + unit);
+            gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+        });
+    }
+
+    fn bind_vertex_array(&self, vertex_array: VertexArrayId) -> Result<(), RendererError> {
+        self.count(|counters| counters.state_changes += 1);
+        let resources = self.resources.borrow();
+        let vertex_array = &resources.vertex_arrays[&vertex_array.0];
+        match &vertex_array.object {
+            Some(object) => self.bind_vertex_array_object(Some(object)),
+            None => self.apply_bindings(vertex_array)?,
+        }
+        self.index_type
+            .set(resources.buffers[&vertex_array.indices.0].index_type);
+        Ok(())
+    }
+
+    fn set_uniform(
+        &self,
+        program: ProgramId,
+        name: &str,
+        value: UniformValue,
+    ) -> Result<(), RendererError> {
+        let program = self.program(program);
+        with_gl!(&self.context, gl => {
+            let location = gl
+                .get_uniform_location(&program, name)
+                .ok_or_else(|| RendererError::MissingUniform(name.to_string()))?;
+            match value {
+                UniformValue::Int(value) => gl.uniform1i(Some(&location), value),
+                UniformValue::Vec4([x, y, z, w]) => gl.uniform4f(Some(&location), x, y, z, w),
+                UniformValue::Mat4(value) => {
+                    gl.uniform_matrix4fv_with_f32_array(Some(&location), false, value.as_slice())
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn draw_triangles(&self, index_count: i32) {
+        self.count(|counters| {
+            counters.draw_calls += 1;
+            counters.triangles += index_count as u32 / 3;
+        });
+        let index_type = self.index_type.get();
+        with_gl!(&self.context, gl => gl.draw_elements_with_i32(
+            WebGlRenderingContext::TRIANGLES,
+            index_count,
+            index_type,
+            0,
+        ));
+    }
+}
+
+fn webgl2_context(canvas: &HtmlCanvasElement) -> Option<Context> {
+    let gl = canvas.get_context("webgl2").ok()??;
+    Some(Context::WebGl2(gl.dyn_into().ok()?))
+}
+
+fn webgl1_context(canvas: &HtmlCanvasElement) -> Result<Context, RendererError> {
+    let gl = canvas
+        .get_context("webgl")
+        .map_err(|_| RendererError::ContextCreation("getContext threw".to_string()))?
+        .ok_or_else(|| {
+            RendererError::ContextCreation("your browser or machine may not support it".to_string())
+        })?
+        .dyn_into::<WebGlRenderingContext>()
+        .map_err(|_| {
+            RendererError::ContextCreation("the context is not a WebGL context".to_string())
+        })?;
+    let extensions = RefCell::new(webgl1_extensions(&gl));
+    Ok(Context::WebGl1 { gl, extensions })
+}
+
+fn webgl1_extensions(gl: &WebGlRenderingContext) -> Extensions {
+    let extension = |name: &str| gl.get_extension(name).ok().flatten();
+    Extensions {
+        vertex_array_object: extension("OES_vertex_array_object").map(JsCast::unchecked_into),
+        element_index_uint: extension("OES_element_index_uint").is_some(),
+    }
+}
+
+/// Fixed state of every context, set at creation and again after a restore.
+fn initialize_state(context: &Context) {
+    with_gl!(context, gl => {
+        gl.clear_depth(1.0);
+        gl.enable(WebGlRenderingContext::DEPTH_TEST);
+        gl.depth_func(WebGlRenderingContext::LEQUAL);
+        gl.enable(WebGlRenderingContext::CULL_FACE);
+        gl.pixel_storei(WebGlRenderingContext::UNPACK_FLIP_Y_WEBGL, 1);
+    });
+}
+
+fn attribute_type(kind: AttributeType) -> u32 {
+    match kind {
+        AttributeType::Float => WebGlRenderingContext::FLOAT,
+        AttributeType::Byte => WebGlRenderingContext::BYTE,
+        AttributeType::UnsignedByte => WebGlRenderingContext::UNSIGNED_BYTE,
+        AttributeType::Short => WebGlRenderingContext::SHORT,
+        AttributeType::UnsignedShort => WebGlRenderingContext::UNSIGNED_SHORT,
+    }
+}
+
+fn upload_buffer(
+    context: &Context,
+    target: u32,
+    usage: u32,
+    contents: &BufferContents,
+) -> Result<WebGlBuffer, RendererError> {
+    with_gl!(context, gl => {
+        let buffer = gl
+            .create_buffer()
+            .ok_or(RendererError::ResourceCreation("buffer"))?;
+        gl.bind_buffer(target, Some(&buffer));
+        // the views must not outlive `contents` and no allocation may happen while they are alive
+        unsafe {
+            match contents {
+                BufferContents::U8(data) => gl.buffer_data_with_u8_array(target, data, usage),
+                BufferContents::U16(data) => gl.buffer_data_with_array_buffer_view(
+                    target,
+                    &js_sys::Uint16Array::view(data),
+                    usage,
+                ),
+                BufferContents::U32(data) => gl.buffer_data_with_array_buffer_view(
+                    target,
+                    &js_sys::Uint32Array::view(data),
+                    usage,
+                ),
+            }
+        }
+        Ok(buffer)
+    })
+}
+
+fn init_shader_program(
+    context: &Context,
+    vss: &str,
+    fss: &str,
+) -> Result<WebGlProgram, RendererError> {
+    let vertex_shader = load_shader(context, ShaderStage::Vertex, vss)?;
+    let fragment_shader = load_shader(context, ShaderStage::Fragment, fss)?;
+
+    with_gl!(context, gl => {
+        let shader_program = gl
+            .create_program()
+            .ok_or(RendererError::ResourceCreation("shader program"))?;
+        gl.attach_shader(&shader_program, &vertex_shader);
+        gl.attach_shader(&shader_program, &fragment_shader);
+        gl.link_program(&shader_program);
+        // the program keeps the shaders alive as long as it needs them
+        gl.delete_shader(Some(&vertex_shader));
+        gl.delete_shader(Some(&fragment_shader));
+
+        if !gl.get_program_parameter(&shader_program, WebGlRenderingContext::LINK_STATUS) {
+            let info_log = gl.get_program_info_log(&shader_program).unwrap_or_default();
+            gl.delete_program(Some(&shader_program));
+            return Err(RendererError::ProgramLink(info_log));
+        }
+
+        Ok(shader_program)
+    })
+}
+
+fn load_shader(
+    context: &Context,
+    stage: ShaderStage,
+    source: &str,
+) -> Result<WebGlShader, RendererError> {
+    let type_ = match stage {
+        ShaderStage::Vertex => WebGlRenderingContext::VERTEX_SHADER,
+        ShaderStage::Fragment => WebGlRenderingContext::FRAGMENT_SHADER,
+    };
+    with_gl!(context, gl => {
+        let shader = gl
+            .create_shader(type_)
+            .ok_or(RendererError::ResourceCreation("shader"))?;
+        gl.shader_source(&shader, source);
+        gl.compile_shader(&shader);
+        if !gl.get_shader_parameter(&shader, WebGlRenderingContext::COMPILE_STATUS) {
+            let log = gl.get_shader_info_log(&shader).unwrap_or_default();
+            gl.delete_shader(Some(&shader));
+            return Err(RendererError::ShaderCompile { stage, log });
+        }
+        Ok(shader)
+    })
+}
+
+fn create_texture(
+    context: &Context,
+    width: i32,
+    height: i32,
+    pixels: &[u8],
+) -> Result<WebGlTexture, RendererError> {
+    let texture = with_gl!(context, gl => {
+        let texture = gl
+            .create_texture()
+            .ok_or(RendererError::ResourceCreation("texture"))?;
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+
+        let level = 0;
+        let internal_format = WebGlRenderingContext::RGBA;
+        let border = 0;
+        let source_format = WebGlRenderingContext::RGBA;
+        let source_type = WebGlRenderingContext::UNSIGNED_BYTE;
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGlRenderingContext::TEXTURE_2D,
+            level,
+            internal_format as i32,
+            width,
+            height,
+            border,
+            source_format,
+            source_type,
+            Some(pixels),
+        )
+        .map_err(|_| RendererError::ResourceCreation("texture data"))?;
+        texture
+    });
+    set_texture_parameters(context, width as u32, height as u32);
+
+    Ok(texture)
+}
+
+fn load_texture(
+    context: &Context,
+    path: &str,
+    error_handler: ErrorHandler,
+    on_loaded: Rc<dyn Fn()>,
+    memory: Rc<Cell<u64>>,
+) -> Result<(WebGlTexture, Option<ImageLoad>), RendererError> {
+    let texture = create_texture(context, 1, 1, &[255, 0, 255, 255])?;
+    memory.set(texture_memory(1, 1));
+
+    let image =
+        HtmlImageElement::new().map_err(|_| RendererError::TextureLoad(path.to_string()))?;
+
+    let on_load = EventListener::once(&image, "load", {
+        let context = context.clone();
+        let texture = texture.clone();
+        let image = image.clone();
+        let error_handler = error_handler.clone();
+        let path = path.to_string();
+        move |_| {
+            // the only upload whose name differs between WebGL1 and WebGL2
+            let upload = match &context {
+                Context::WebGl1 { gl, .. } => {
+                    gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+                    gl.tex_image_2d_with_u32_and_u32_and_image(
+                        WebGlRenderingContext::TEXTURE_2D,
+                        0,
+                        WebGlRenderingContext::RGBA as i32,
+                        WebGlRenderingContext::RGBA,
+                        WebGlRenderingContext::UNSIGNED_BYTE,
+                        &image,
+                    )
+                }
+                Context::WebGl2(gl) => {
+                    gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+                    gl.tex_image_2d_with_u32_and_u32_and_html_image_element(
+                        WebGlRenderingContext::TEXTURE_2D,
+                        0,
+                        WebGlRenderingContext::RGBA as i32,
+                        WebGlRenderingContext::RGBA,
+                        WebGlRenderingContext::UNSIGNED_BYTE,
+                        &image,
+                    )
+                }
+            };
+            match upload {
+                Ok(()) => {
+                    set_texture_parameters(&context, image.width(), image.height());
+                    memory.set(texture_memory(image.width(), image.height()));
+                    on_loaded();
+                }
+                Err(_) => error_handler(RendererError::TextureLoad(path)),
+            }
+        }
+    });
+    let on_error = EventListener::once(&image, "error", {
+        let path = path.to_string();
+        move |_| error_handler(RendererError::TextureLoad(path))
+    });
+
+    image.set_cross_origin(Some("anonymous"));
+    image.set_src(path);
+
+    let loading = ImageLoad {
+        _on_load: on_load,
+        _on_error: on_error,
+    };
+    Ok((texture, Some(loading)))
+}
+
+/// The color texture is filtered with the nearest texel, its pixels read back as written.
+fn create_framebuffer(
+    context: &Context,
+    width: i32,
+    height: i32,
+) -> Result<Framebuffer, RendererError> {
+    with_gl!(context, gl => {
+        let color = gl
+            .create_texture()
+            .ok_or(RendererError::ResourceCreation("framebuffer texture"))?;
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&color));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGlRenderingContext::TEXTURE_2D,
+            0,
+            WebGlRenderingContext::RGBA as i32,
+            width,
+            height,
+            0,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            None,
+        )
+        .map_err(|_| RendererError::ResourceCreation("framebuffer texture"))?;
+        for (parameter, value) in [
+            (WebGlRenderingContext::TEXTURE_MIN_FILTER, WebGlRenderingContext::NEAREST),
+            (WebGlRenderingContext::TEXTURE_MAG_FILTER, WebGlRenderingContext::NEAREST),
+            (WebGlRenderingContext::TEXTURE_WRAP_S, WebGlRenderingContext::CLAMP_TO_EDGE),
+            (WebGlRenderingContext::TEXTURE_WRAP_T, WebGlRenderingContext::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, parameter, value as i32);
+        }
+
+        let depth = gl
+            .create_renderbuffer()
+            .ok_or(RendererError::ResourceCreation("framebuffer depth"))?;
+        gl.bind_renderbuffer(WebGlRenderingContext::RENDERBUFFER, Some(&depth));
+        gl.renderbuffer_storage(
+            WebGlRenderingContext::RENDERBUFFER,
+            WebGlRenderingContext::DEPTH_COMPONENT16,
+            width,
+            height,
+        );
+
+        let framebuffer = gl
+            .create_framebuffer()
+            .ok_or(RendererError::ResourceCreation("framebuffer"))?;
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGlRenderingContext::FRAMEBUFFER,
+            WebGlRenderingContext::COLOR_ATTACHMENT0,
+            WebGlRenderingContext::TEXTURE_2D,
+            Some(&color),
+            0,
+        );
+        gl.framebuffer_renderbuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            WebGlRenderingContext::DEPTH_ATTACHMENT,
+            WebGlRenderingContext::RENDERBUFFER,
+            Some(&depth),
+        );
+        let status = gl.check_framebuffer_status(WebGlRenderingContext::FRAMEBUFFER);
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None);
+
+        let framebuffer = Framebuffer {
+            framebuffer,
+            color,
+            depth,
+            width,
+            height,
+        };
+        if status != WebGlRenderingContext::FRAMEBUFFER_COMPLETE {
+            delete_framebuffer(context, &framebuffer);
+            return Err(RendererError::ResourceCreation("framebuffer"));
+        }
+        Ok(framebuffer)
+    })
+}
+
+fn delete_framebuffer(context: &Context, framebuffer: &Framebuffer) {
+    with_gl!(context, gl => {
+        gl.delete_framebuffer(Some(&framebuffer.framebuffer));
+        gl.delete_texture(Some(&framebuffer.color));
+        gl.delete_renderbuffer(Some(&framebuffer.depth));
+    });
+}
+
+/// Mipmaps for power of two textures, clamped linear filtering for the others.
+fn set_texture_parameters(context: &Context, width: u32, height: u32) {
+    with_gl!(context, gl => {
+        if is_power_of_2(width) && is_power_of_2(height) {
+            gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
+        } else {
+            gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                WebGlRenderingContext::TEXTURE_MIN_FILTER,
+                WebGlRenderingContext::LINEAR as i32,
+            );
+            gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                WebGlRenderingContext::TEXTURE_WRAP_S,
+                WebGlRenderingContext::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                WebGlRenderingContext::TEXTURE_WRAP_T,
+                WebGlRenderingContext::CLAMP_TO_EDGE as i32,
+            );
+        }
+    });
+}
+
+fn texture_memory(width: u32, height: u32) -> u64 {
+    let base = width as u64 * height as u64 * 4;
+    if is_power_of_2(width) && is_power_of_2(height) {
+        base * 4 / 3
+    } else {
+        base
+    }
+}
+
+fn is_power_of_2(value: u32) -> bool {
+    value & (value - 1) == 0
+}
@@ -0,0 +1,560 @@
+use crate::options::RendererOptions;
+use crate::primitive::lerp_angle;
+use nalgebra::{Matrix4, Orthographic3, Perspective3, Point3, Rotation3, Unit, Vector2, Vector3};
+use std::f32::consts::{FRAC_PI_2, PI};
+use wasm_bindgen::prelude::*;
+
+/// How the scene is projected on screen.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Projection {
+    /// Flat view, the zoom scales the visible part of the scene.
+    Orthographic,
+    /// 3D view, the zoom scales the distance to the target.
+    Perspective,
+}
+
+/// Camera orbiting around a target point, owns the view and the projection of the scene.
+///
+/// With no yaw and pitch the camera looks down the z axis with y going up, which is the 2D view
+/// of the orthographic projection. Screen positions are in pixels from the top left corner of
+/// the viewport and map to world positions on the z = 0 plane.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Camera {
+    projection: Projection,
+    target: Vector3<f32>,
+    distance: f32,
+    /// Rotation around the y axis, in radians.
+    yaw: f32,
+    /// Rotation above the xz plane, in radians.
+    pitch: f32,
+    /// Rotation around the view direction, in radians.
+    roll: f32,
+    /// Scales the field of view, bigger values show more of the scene.
+    zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    field_of_view: f32,
+    z_near: f32,
+    z_far: f32,
+    viewport_width: i32,
+    viewport_height: i32,
+}
+
+/// Half line starting at `origin`, `direction` is normalized unless the ray was transformed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// Where the ray crosses the plane through `point` with the given `normal`.
+    pub fn intersect_plane(
+        &self,
+        point: Vector3<f32>,
+        normal: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        let denominator = self.direction.dot(&normal);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let distance = (point - self.origin).dot(&normal) / denominator;
+        (distance >= 0.0).then(|| self.origin + self.direction * distance)
+    }
+
+    /// The same ray in other coordinates. The direction is not normalized again, so distances
+    /// along it stay comparable with the ones along the original ray.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Ray {
+        Ray {
+            origin: matrix.transform_point(&Point3::from(self.origin)).coords,
+            direction: matrix.transform_vector(&self.direction),
+        }
+    }
+}
+
+impl Camera {
+    /// Largest zoom limit, the orthographic field of view nears a half turn beyond it.
+    pub const MAX_ZOOM: f32 = 3.9;
+
+    /// Distance beyond which nothing is drawn.
+    pub const Z_FAR: f32 = 100.0;
+
+    /// Perspective zoom stops dollying out here, the target has to stay in front of the far
+    /// plane with room for the scene behind it.
+    const MAX_EYE_DISTANCE: f32 = Camera::Z_FAR * 0.9;
+
+    /// Pitch is kept short of the poles, where the up direction is undefined.
+    const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+    /// Camera at `position` looking down the z axis, its target is below it on the z = 0 plane.
+    pub fn new(position: Vector3<f32>, viewport_width: i32, viewport_height: i32) -> Camera {
+        let (min_zoom, max_zoom) = RendererOptions::DEFAULT_ZOOM_LIMITS;
+        let mut camera = Camera {
+            projection: Projection::Orthographic,
+            target: Vector3::new(position.x, position.y, 0.0),
+            distance: position.z,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            zoom: 1.0,
+            min_zoom,
+            max_zoom,
+            field_of_view: 45.0 * PI / 180.0,
+            z_near: 0.1,
+            z_far: Camera::Z_FAR,
+            viewport_width: 1,
+            viewport_height: 1,
+        };
+        camera.set_viewport(viewport_width, viewport_height);
+        camera
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Position of the eye.
+    pub fn position(&self) -> Vector3<f32> {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let offset = Vector3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw);
+        self.target + offset * self.eye_distance()
+    }
+
+    pub fn target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Vector3<f32>) {
+        self.target = target;
+    }
+
+    /// Move the target, and the eye with it.
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        self.target += offset;
+    }
+
+    /// Scroll the view by `pixels` on screen, the scene moves the other way.
+    pub fn scroll(&mut self, pixels: Vector2<f32>) {
+        let center = Vector2::new(self.viewport_width as f32, self.viewport_height as f32) / 2.0;
+        self.target +=
+            self.screen_to_focus_plane(center + pixels) - self.screen_to_focus_plane(center);
+    }
+
+    /// Turn around the target by `yaw` and `pitch` radians.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = (self.yaw + yaw) % (2.0 * PI);
+        self.pitch = (self.pitch + pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+
+    /// Turn the view around its direction by `angle` radians, the scene turns clockwise on
+    /// screen around `anchor` for positive angles.
+    pub fn rotate_around(&mut self, angle: f32, anchor: Vector2<f32>) {
+        let before = self.screen_to_focus_plane(anchor);
+        self.roll = (self.roll + angle) % (2.0 * PI);
+        let after = self.screen_to_focus_plane(anchor);
+        self.target += before - after;
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// The zoom is kept within the limits.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+    }
+
+    pub fn zoom_limits(&self) -> (f32, f32) {
+        (self.min_zoom, self.max_zoom)
+    }
+
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        self.min_zoom = min;
+        self.max_zoom = max;
+        self.set_zoom(self.zoom);
+    }
+
+    /// Multiply the zoom by `factor` while the world position under `anchor` stays in place.
+    pub fn zoom_around(&mut self, factor: f32, anchor: Vector2<f32>) {
+        let before = self.screen_to_world(anchor);
+        self.set_zoom(self.zoom * factor);
+        let after = self.screen_to_world(anchor);
+        // moving along the z = 0 plane keeps the plane in place on screen
+        self.target += before - after;
+    }
+
+    pub fn viewport_width(&self) -> i32 {
+        self.viewport_width
+    }
+
+    pub fn viewport_height(&self) -> i32 {
+        self.viewport_height
+    }
+
+    /// An empty viewport, like the one of a hidden canvas, is handled as a single pixel.
+    pub fn set_viewport(&mut self, width: i32, height: i32) {
+        self.viewport_width = width.max(1);
+        self.viewport_height = height.max(1);
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.viewport_width as f32 / self.viewport_height as f32
+    }
+
+    /// The orthographic projection does not depend on the distance, so only the perspective
+    /// one dollies.
+    fn eye_distance(&self) -> f32 {
+        match self.projection {
+            Projection::Orthographic => self.distance,
+            Projection::Perspective => (self.distance * self.zoom).min(Self::MAX_EYE_DISTANCE),
+        }
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let eye = self.position();
+        let direction = Unit::new_normalize(self.target - eye);
+        let up = Rotation3::from_axis_angle(&direction, -self.roll) * Vector3::y();
+        Matrix4::look_at_rh(&Point3::from(eye), &Point3::from(self.target), &up)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Orthographic => Orthographic3::from_fov(
+                self.aspect(),
+                self.field_of_view * self.zoom,
+                self.z_near,
+                self.z_far,
+            )
+            .to_homogeneous(),
+            Projection::Perspective => {
+                Perspective3::new(self.aspect(), self.field_of_view, self.z_near, self.z_far)
+                    .to_homogeneous()
+            }
+        }
+    }
+
+    /// Ray from the near plane through the screen position, to pick what is under it. `None`
+    /// when the projection is degenerate and nothing is under the screen position.
+    pub fn screen_ray(&self, screen: Vector2<f32>) -> Option<Ray> {
+        let x_clip = screen.x / self.viewport_width as f32 * 2.0 - 1.0;
+        let y_clip = 1.0 - screen.y / self.viewport_height as f32 * 2.0;
+        let inverse = (self.projection_matrix() * self.view_matrix()).try_inverse()?;
+        let near = inverse.transform_point(&Point3::new(x_clip, y_clip, -1.0));
+        let far = inverse.transform_point(&Point3::new(x_clip, y_clip, 1.0));
+        let ray = Ray {
+            origin: near.coords,
+            direction: (far - near).try_normalize(f32::EPSILON)?,
+        };
+        // a camera sitting on its target has no view direction, its matrices are all NaN
+        let finite = ray
+            .origin
+            .iter()
+            .chain(ray.direction.iter())
+            .all(|v| v.is_finite());
+        Some(ray).filter(|_| finite)
+    }
+
+    /// World position on the z = 0 plane under the screen position. When the plane is not
+    /// under it, the position at the depth of the target is used instead.
+    pub fn screen_to_world(&self, screen: Vector2<f32>) -> Vector3<f32> {
+        self.screen_ray(screen)
+            .and_then(|ray| ray.intersect_plane(Vector3::zeros(), Vector3::z()))
+            .unwrap_or_else(|| self.screen_to_focus_plane(screen))
+    }
+
+    /// World position under the screen position on the plane through the target that faces
+    /// the camera, panning moves the camera along it.
+    pub fn screen_to_focus_plane(&self, screen: Vector2<f32>) -> Vector3<f32> {
+        let normal = self.position() - self.target;
+        self.screen_ray(screen)
+            .and_then(|ray| ray.intersect_plane(self.target, normal))
+            .unwrap_or(self.target)
+    }
+
+    /// Screen position of a world position. Positions behind a perspective camera come out
+    /// mirrored.
+    pub fn world_to_screen(&self, world: Vector3<f32>) -> Vector2<f32> {
+        let clip =
+            (self.projection_matrix() * self.view_matrix()).transform_point(&Point3::from(world));
+        Vector2::new(
+            (clip.x + 1.0) * 0.5 * self.viewport_width as f32,
+            (1.0 - clip.y) * 0.5 * self.viewport_height as f32,
+        )
+    }
+
+    /// Bounds of the part of the z = 0 plane covered by the viewport. Corners that don't see
+    /// the plane, above the horizon, count at the depth of the target.
+    pub fn visible_rect(&self) -> WorldRect {
+        let (width, height) = (self.viewport_width as f32, self.viewport_height as f32);
+        let corners = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(width, 0.0),
+            Vector2::new(0.0, height),
+            Vector2::new(width, height),
+        ];
+        let mut rect = WorldRect {
+            min_x: f32::INFINITY,
+            min_y: f32::INFINITY,
+            max_x: f32::NEG_INFINITY,
+            max_y: f32::NEG_INFINITY,
+        };
+        for corner in corners {
+            let world = self.screen_to_world(corner);
+            rect.min_x = rect.min_x.min(world.x);
+            rect.min_y = rect.min_y.min(world.y);
+            rect.max_x = rect.max_x.max(world.x);
+            rect.max_y = rect.max_y.max(world.y);
+        }
+        rect
+    }
+
+    /// Take the view between `from` (`t` = 0) and `to` (1): target, distance, orientation and
+    /// zoom. The viewport, projection and limits stay.
+    pub fn blend_views(&mut self, from: &Camera, to: &Camera, t: f32) {
+        self.target = from.target.lerp(&to.target, t);
+        self.distance = from.distance + (to.distance - from.distance) * t;
+        self.yaw = lerp_angle(from.yaw, to.yaw, t);
+        self.pitch = from.pitch + (to.pitch - from.pitch) * t;
+        self.roll = lerp_angle(from.roll, to.roll, t);
+        // zooming geometrically looks like a constant speed
+        self.set_zoom(from.zoom * (to.zoom / from.zoom).powf(t));
+    }
+
+    /// Copy of the camera centered on `rect` and zoomed so that `rect` just fits the viewport,
+    /// as far as the zoom limits allow.
+    pub fn fit(&self, rect: WorldRect) -> Camera {
+        let mut camera = self.clone();
+        camera.target = Vector3::new(
+            (rect.min_x + rect.max_x) / 2.0,
+            (rect.min_y + rect.max_y) / 2.0,
+            0.0,
+        );
+        let width = (rect.max_x - rect.min_x).max(f32::EPSILON);
+        let height = (rect.max_y - rect.min_y).max(f32::EPSILON);
+        let required =
+            (self.viewport_width as f32 / width).min(self.viewport_height as f32 / height);
+        camera.zoom = camera.zoom_for_pixels_per_unit(required);
+        camera
+    }
+
+    /// Enlarge the scene on screen by `scale` while the world position under `anchor` stays
+    /// in place. Unlike [`Camera::zoom_around`] the size on screen follows `scale` exactly.
+    pub fn magnify_around(&mut self, scale: f32, anchor: Vector2<f32>) {
+        let zoom = self.zoom_for_pixels_per_unit(self.pixels_per_unit() * scale);
+        self.zoom_around(zoom / self.zoom, anchor);
+    }
+
+    /// Smallest zoom within the limits that draws at most `required` pixels per world unit.
+    fn zoom_for_pixels_per_unit(&self, required: f32) -> f32 {
+        let mut camera = self.clone();
+        // pixels per unit shrink as the zoom grows, bisect on the zoom logarithm
+        let (mut low, mut high) = (self.min_zoom.ln(), self.max_zoom.ln());
+        for _ in 0..32 {
+            let middle = (low + high) / 2.0;
+            camera.zoom = middle.exp();
+            if camera.pixels_per_unit() > required {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        high.exp()
+    }
+
+    /// Size in pixels of one world unit on screen, at the depth of the target.
+    pub fn pixels_per_unit(&self) -> f32 {
+        let view = self.view_matrix();
+        let right = Vector3::new(view.m11, view.m12, view.m13);
+        let center = self.world_to_screen(self.target);
+        (self.world_to_screen(self.target + right) - center).magnitude()
+    }
+}
+
+/// Axis aligned rectangle in world space.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct WorldRect {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+#[wasm_bindgen]
+impl WorldRect {
+    #[wasm_bindgen(constructor)]
+    pub fn new(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> WorldRect {
+        WorldRect {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(Vector3::new(3.0, -2.0, 10.0), 800, 600)
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn viewport_center_looks_at_the_camera_position() {
+        let camera = camera();
+
+        let center = camera.screen_to_world(Vector2::new(400.0, 300.0));
+
+        assert_near(center.x, 3.0);
+        assert_near(center.y, -2.0);
+        let screen = camera.world_to_screen(Vector3::new(3.0, -2.0, 0.0));
+        assert_near(screen.x, 400.0);
+        assert_near(screen.y, 300.0);
+    }
+
+    #[test]
+    fn screen_and_world_positions_round_trip() {
+        let mut camera = camera();
+        camera.set_zoom(0.5);
+
+        let world = camera.screen_to_world(Vector2::new(120.0, 45.0));
+        let screen = camera.world_to_screen(world);
+
+        assert_near(screen.x, 120.0);
+        assert_near(screen.y, 45.0);
+        // screen y goes down, world y goes up
+        assert!(world.x < 3.0 && world.y > -2.0);
+    }
+
+    #[test]
+    fn visible_rect_matches_pixels_per_unit() {
+        let camera = camera();
+
+        let rect = camera.visible_rect();
+        let pixels_per_unit = camera.pixels_per_unit();
+
+        assert_near((rect.max_x - rect.min_x) * pixels_per_unit, 800.0);
+        assert_near((rect.max_y - rect.min_y) * pixels_per_unit, 600.0);
+        assert_near((rect.min_x + rect.max_x) / 2.0, 3.0);
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_in_place() {
+        for projection in [Projection::Orthographic, Projection::Perspective] {
+            let mut camera = camera();
+            camera.set_projection(projection);
+            camera.orbit(0.3, 0.2);
+            let anchor = Vector2::new(650.0, 80.0);
+            let world = camera.screen_to_world(anchor);
+            let pixels_per_unit = camera.pixels_per_unit();
+
+            camera.zoom_around(0.5, anchor);
+
+            let moved = camera.screen_to_world(anchor);
+            assert_near(moved.x, world.x);
+            assert_near(moved.y, world.y);
+            assert!(camera.pixels_per_unit() > pixels_per_unit);
+        }
+    }
+
+    #[test]
+    fn rotation_turns_the_scene_clockwise_around_the_anchor() {
+        let mut camera = camera();
+        let anchor = Vector2::new(200.0, 300.0);
+        let world = camera.screen_to_world(anchor);
+        let right = camera.screen_to_world(anchor + Vector2::new(100.0, 0.0));
+
+        camera.rotate_around(0.5, anchor);
+
+        let moved = camera.screen_to_world(anchor);
+        assert_near(moved.x, world.x);
+        assert_near(moved.y, world.y);
+        // clockwise on screen, where y goes down
+        let turned = camera.world_to_screen(right) - anchor;
+        assert_near(turned.y.atan2(turned.x), 0.5);
+    }
+
+    #[test]
+    fn zoom_stays_within_limits() {
+        let mut camera = camera();
+        camera.set_zoom_limits(0.5, 2.0);
+
+        camera.set_zoom(10.0);
+        assert_eq!(camera.zoom(), 2.0);
+        camera.zoom_around(0.01, Vector2::new(0.0, 0.0));
+        assert_eq!(camera.zoom(), 0.5);
+    }
+
+    #[test]
+    fn perspective_unprojection_round_trips_in_depth() {
+        let mut camera = camera();
+        camera.set_projection(Projection::Perspective);
+        camera.orbit(0.7, 0.4);
+
+        for world in [
+            Vector3::new(3.0, -2.0, 0.0),
+            Vector3::new(4.0, -1.0, 1.5),
+            Vector3::new(1.0, -3.0, -2.0),
+        ] {
+            let screen = camera.world_to_screen(world);
+            let ray = camera.screen_ray(screen).unwrap();
+            // the point lies on the ray through its own screen position
+            let along = (world - ray.origin).dot(&ray.direction);
+            let closest = ray.origin + ray.direction * along;
+            assert!((closest - world).magnitude() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn perspective_zoom_keeps_the_target_before_the_far_plane() {
+        let mut camera = Camera::new(Vector3::new(0.0, 0.0, 99.0), 800, 600);
+        camera.set_projection(Projection::Perspective);
+        camera.set_zoom_limits(0.1, Camera::MAX_ZOOM);
+        camera.set_zoom(Camera::MAX_ZOOM);
+
+        let clip = camera.projection_matrix() * camera.view_matrix() * camera.target().push(1.0);
+        let depth = clip.z / clip.w;
+        assert!(depth > -1.0 && depth < 1.0, "depth {}", depth);
+        let screen = camera.world_to_screen(camera.target());
+        assert_near(screen.x, 400.0);
+        assert_near(screen.y, 300.0);
+    }
+
+    #[test]
+    fn degenerate_projections_have_no_screen_ray() {
+        let camera = Camera::new(Vector3::zeros(), 800, 600);
+
+        assert!(camera.screen_ray(Vector2::new(400.0, 300.0)).is_none());
+        assert_eq!(
+            camera.screen_to_world(Vector2::new(400.0, 300.0)),
+            camera.target()
+        );
+    }
+
+    #[test]
+    fn orbiting_keeps_the_target_centered() {
+        let mut camera = camera();
+        camera.set_projection(Projection::Perspective);
+
+        camera.orbit(1.0, 0.5);
+        camera.orbit(0.0, 10.0);
+
+        let center = camera.world_to_screen(camera.target());
+        assert_near(center.x, 400.0);
+        assert_near(center.y, 300.0);
+        assert_near((camera.position() - camera.target()).magnitude(), 10.0);
+        assert!(camera.position().y > camera.target().y);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::camera::{Camera, WorldRect};
+use nalgebra::{Vector2, Vector3};
+
+/// Camera movements that go on after the input that started them: eased zoom, pan inertia and
+/// flights to a part of the scene. They advance by the fixed simulation steps.
+pub(crate) struct CameraMotion {
+    zoom: Option<ZoomTransition>,
+    /// World units per second the camera keeps moving by once a drag is released.
+    pan_velocity: Vector3<f32>,
+    /// When the drag last moved the camera.
+    last_pan: Option<f64>,
+    /// When the drag was last tracked, to measure its speed.
+    last_tracked: Option<f64>,
+    pan_friction: f32,
+    flight: Option<Flight>,
+}
+
+#[derive(Copy, Clone)]
+struct ZoomTransition {
+    target: f32,
+    /// Screen position that keeps its world position while zooming.
+    anchor: Vector2<f32>,
+}
+
+struct Flight {
+    from: Camera,
+    to: Camera,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraMotion {
+    /// Time constant of the zoom easing, in seconds.
+    const ZOOM_SMOOTHING: f32 = 0.06;
+    /// A drag released after holding still for longer than this, in milliseconds, does not glide.
+    const PAN_RELEASE_DELAY: f64 = 50.0;
+    /// Inertia stops below this speed in pixels per second.
+    const MIN_PAN_SPEED: f32 = 10.0;
+    /// Duration of the first tracked frame of a drag, and the longest one accounted for.
+    const DEFAULT_FRAME_TIME: f32 = 1.0 / 60.0;
+    const MAX_FRAME_TIME: f32 = 0.1;
+
+    pub fn new(pan_friction: f32) -> CameraMotion {
+        CameraMotion {
+            zoom: None,
+            pan_velocity: Vector3::zeros(),
+            last_pan: None,
+            last_tracked: None,
+            pan_friction,
+            flight: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.zoom.is_some() || self.flight.is_some() || self.pan_velocity != Vector3::zeros()
+    }
+
+    /// Ease the zoom towards `factor` times its current destination.
+    pub fn zoom_by(&mut self, camera: &Camera, factor: f32, anchor: Vector2<f32>) {
+        self.flight = None;
+        let current = self.zoom.as_ref().map_or(camera.zoom(), |zoom| zoom.target);
+        let (min, max) = camera.zoom_limits();
+        self.zoom = Some(ZoomTransition {
+            target: (current * factor).clamp(min, max),
+            anchor,
+        });
+    }
+
+    /// A drag takes over the camera.
+    pub fn stop(&mut self) {
+        self.zoom = None;
+        self.flight = None;
+        self.pan_velocity = Vector3::zeros();
+        self.last_pan = None;
+        self.last_tracked = None;
+    }
+
+    /// Account for a drag moving the camera by `offset` since the frame it was last tracked.
+    pub fn track_pan(&mut self, offset: Vector3<f32>, timestamp: f64) {
+        let delta = match self.last_tracked {
+            Some(last) => (((timestamp - last) * 0.001) as f32).min(Self::MAX_FRAME_TIME),
+            None => Self::DEFAULT_FRAME_TIME,
+        };
+        self.last_tracked = Some(timestamp);
+        if delta <= 0.0 {
+            return;
+        }
+        // smoothed over a few frames so a single jerky one does not decide the glide
+        self.pan_velocity = self.pan_velocity.lerp(&(offset / delta), 0.5);
+        if offset != Vector3::zeros() {
+            self.last_pan = Some(timestamp);
+        }
+    }
+
+    /// The drag ended, the camera glides on if it was still moving.
+    pub fn release_pan(&mut self, timestamp: f64) {
+        let moving = self
+            .last_pan
+            .is_some_and(|last| timestamp - last <= Self::PAN_RELEASE_DELAY);
+        if !moving {
+            self.pan_velocity = Vector3::zeros();
+        }
+        self.last_pan = None;
+        self.last_tracked = None;
+    }
+
+    /// Animate the camera over `duration` seconds until `rect` fills the viewport.
+    pub fn fly_to(&mut self, camera: &Camera, rect: WorldRect, duration: f32) {
+        self.fly_to_view(camera, camera.fit(rect), duration);
+    }
+
+    /// Animate the camera over `duration` seconds to the view of `destination`.
+    pub fn fly_to_view(&mut self, camera: &Camera, destination: Camera, duration: f32) {
+        self.stop();
+        self.flight = Some(Flight {
+            from: camera.clone(),
+            to: destination,
+            elapsed: 0.0,
+            duration,
+        });
+    }
+
+    /// One simulation step of `delta` seconds, inertia waits for the drag to be released.
+    pub fn update(&mut self, camera: &mut Camera, delta: f32, dragging: bool) {
+        if let Some(zoom) = self.zoom {
+            let remaining = zoom.target / camera.zoom();
+            let factor = if remaining.ln().abs() < 1e-3 {
+                self.zoom = None;
+                remaining
+            } else {
+                remaining.powf(1.0 - (-delta / Self::ZOOM_SMOOTHING).exp())
+            };
+            camera.zoom_around(factor, zoom.anchor);
+        }
+
+        if !dragging && self.pan_velocity != Vector3::zeros() {
+            camera.translate(self.pan_velocity * delta);
+            self.pan_velocity *= (-self.pan_friction * delta).exp();
+            if self.pan_velocity.magnitude() * camera.pixels_per_unit() < Self::MIN_PAN_SPEED {
+                self.pan_velocity = Vector3::zeros();
+            }
+        }
+
+        if let Some(flight) = &mut self.flight {
+            flight.elapsed += delta;
+            let progress = if flight.duration > 0.0 {
+                (flight.elapsed / flight.duration).min(1.0)
+            } else {
+                1.0
+            };
+            let eased = progress * progress * (3.0 - 2.0 * progress);
+            camera.blend_views(&flight.from, &flight.to, eased);
+            if progress >= 1.0 {
+                self.flight = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup;
+    use crate::{simulate, Renderer};
+
+    fn camera() -> Camera {
+        Camera::new(Vector3::new(0.0, 0.0, 10.0), 800, 600)
+    }
+
+    fn run(motion: &mut CameraMotion, camera: &mut Camera, frames: u32) {
+        for _ in 0..frames {
+            motion.update(camera, 1.0 / 60.0, false);
+        }
+    }
+
+    #[test]
+    fn zoom_eases_to_its_destination_around_the_anchor() {
+        let mut camera = camera();
+        let mut motion = CameraMotion::new(4.0);
+        let anchor = Vector2::new(600.0, 150.0);
+        let grabbed = camera.screen_to_world(anchor);
+
+        motion.zoom_by(&camera, 1.1, anchor);
+        motion.zoom_by(&camera, 1.1, anchor);
+        run(&mut motion, &mut camera, 1);
+        let first_frame = camera.zoom();
+        run(&mut motion, &mut camera, 60);
+
+        assert!(first_frame > 1.0 && first_frame < 1.21);
+        assert!((camera.zoom() - 1.21).abs() < 1e-4);
+        assert!(!motion.is_active());
+        assert!((camera.screen_to_world(anchor) - grabbed).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn released_drag_glides_and_stops() {
+        let mut camera = camera();
+        let mut motion = CameraMotion::new(4.0);
+        motion.track_pan(Vector3::new(0.1, 0.0, 0.0), 1000.0);
+        motion.release_pan(1010.0);
+        assert!(motion.is_active());
+
+        run(&mut motion, &mut camera, 5);
+        let halfway = camera.target().x;
+        run(&mut motion, &mut camera, 600);
+
+        assert!(halfway > 0.0);
+        assert!(camera.target().x > halfway);
+        assert!(!motion.is_active());
+    }
+
+    #[test]
+    fn drag_held_still_before_release_does_not_glide() {
+        let mut motion = CameraMotion::new(4.0);
+        motion.track_pan(Vector3::new(0.1, 0.0, 0.0), 1000.0);
+
+        motion.release_pan(1200.0);
+
+        assert!(!motion.is_active());
+    }
+
+    #[test]
+    fn flight_ends_with_the_rect_in_view() {
+        let mut camera = camera();
+        let mut motion = CameraMotion::new(4.0);
+        let rect = WorldRect::new(20.0, 10.0, 28.0, 13.0);
+
+        motion.fly_to(&camera, rect, 0.5);
+        run(&mut motion, &mut camera, 15);
+        let halfway = camera.target();
+        run(&mut motion, &mut camera, 30);
+
+        assert!(halfway.x > 0.0 && halfway.x < 24.0);
+        assert!(!motion.is_active());
+        let visible = camera.visible_rect();
+        assert!(visible.min_x <= 20.001 && visible.max_x >= 27.999);
+        assert!(visible.min_y <= 10.0 && visible.max_y >= 13.0);
+        assert!((visible.max_x - visible.min_x - 8.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn reset_view_flies_back_to_the_start() {
+        let (_, mut renderer) = setup(|_| {});
+        renderer.camera.scroll(Vector2::new(250.0, 40.0));
+        renderer.camera.set_zoom(2.0);
+
+        renderer.key_down("Home", false);
+        for frame in 0..40 {
+            simulate(&mut renderer, frame as f64 * 16.0);
+        }
+
+        assert!(
+            (renderer.camera.target() - renderer.home.target())
+                .abs()
+                .max()
+                < 1e-4
+        );
+        assert!((renderer.camera.zoom() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn camera_motions_do_not_depend_on_the_display_rate() {
+        let camera_after_one_second = |display_rate: f64, input: &dyn Fn(&mut Renderer)| {
+            let (_, mut renderer) = setup(|_| {});
+            input(&mut renderer);
+            for frame in 0..=display_rate as u32 {
+                simulate(&mut renderer, frame as f64 * 1000.0 / display_rate);
+            }
+            renderer.camera
+        };
+        let zoom = |renderer: &mut Renderer| renderer.wheel_input.zoom = 0.5;
+        let glide = |renderer: &mut Renderer| {
+            renderer
+                .camera_motion
+                .track_pan(Vector3::new(0.2, 0.1, 0.0), 0.0);
+            renderer.camera_motion.release_pan(0.0);
+        };
+        let keys = |renderer: &mut Renderer| {
+            renderer.key_down("ArrowRight", false);
+        };
+        let flight = |renderer: &mut Renderer| {
+            let camera = renderer.camera.clone();
+            let rect = WorldRect::new(20.0, 10.0, 28.0, 13.0);
+            renderer.camera_motion.fly_to(&camera, rect, 0.5);
+        };
+
+        let start = camera_after_one_second(60.0, &|_| {});
+        for input in [&zoom as &dyn Fn(&mut Renderer), &glide, &keys, &flight] {
+            let slow = camera_after_one_second(30.0, input);
+            assert_ne!(slow, start);
+            assert_eq!(slow, camera_after_one_second(144.0, input));
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use crate::error::RendererError;
 use crate::Renderer;
 
 pub trait Drawable {
-    fn draw(&self, renderer: &Renderer);
-}
\ No newline at end of file
+    fn draw(&self, renderer: &Renderer) -> Result<(), RendererError>;
+}
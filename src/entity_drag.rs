@@ -0,0 +1,268 @@
+use crate::camera::Camera;
+use crate::picking::Bounds;
+use nalgebra::{Vector2, Vector3};
+use wasm_bindgen::prelude::*;
+
+/// Pixels on screen within which a dragged entity snaps to the edge of another one.
+const SNAP_PIXELS: f32 = 8.0;
+
+/// How dragged entities snap into place.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub(crate) struct SnapSettings {
+    /// Spacing of the grid positions snap to, 0 turns the grid off.
+    pub grid: f32,
+    /// Line the edges of the dragged entities up with the edges of the other ones.
+    pub to_entities: bool,
+}
+
+/// One entity moved by a drag, given to the host page once the drag ends.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EntityMove {
+    /// Index of the entity in the scene.
+    pub entity: usize,
+    pub(crate) from: Vector3<f32>,
+    pub(crate) to: Vector3<f32>,
+}
+
+impl EntityMove {
+    pub(crate) fn new(entity: usize, from: Vector3<f32>, to: Vector3<f32>) -> EntityMove {
+        EntityMove { entity, from, to }
+    }
+
+    /// The move back.
+    pub(crate) fn reversed(&self) -> EntityMove {
+        EntityMove::new(self.entity, self.to, self.from)
+    }
+}
+
+#[wasm_bindgen]
+impl EntityMove {
+    /// Position before the drag, as `[x, y, z]`.
+    #[wasm_bindgen(getter)]
+    pub fn from(&self) -> Vec<f32> {
+        self.from.as_slice().to_vec()
+    }
+
+    /// Position after the drag.
+    #[wasm_bindgen(getter)]
+    pub fn to(&self) -> Vec<f32> {
+        self.to.as_slice().to_vec()
+    }
+}
+
+/// Selected entities following the pointer on the plane of the grabbed entity, parallel to
+/// z = 0.
+pub(crate) struct EntityDrag {
+    /// Dragged entities with their position when the drag started.
+    entities: Vec<(usize, Vector3<f32>)>,
+    /// World position under the pointer when the drag started.
+    grab: Vector3<f32>,
+    /// Box around the dragged entities when the drag started.
+    bounds: Bounds,
+    /// Boxes around the entities left in place, their edges attract the dragged ones.
+    others: Vec<Bounds>,
+    offset: Vector3<f32>,
+}
+
+impl EntityDrag {
+    /// `entities` are the dragged ones with their position and world bounds, grabbed one first.
+    pub fn new(
+        entities: Vec<(usize, Vector3<f32>, Bounds)>,
+        others: Vec<Bounds>,
+        grab: Vector3<f32>,
+    ) -> EntityDrag {
+        let corners: Vec<_> = entities
+            .iter()
+            .flat_map(|(_, _, bounds)| [bounds.min, bounds.max])
+            .collect();
+        EntityDrag {
+            bounds: Bounds::around(&corners),
+            entities: entities
+                .into_iter()
+                .map(|(entity, position, _)| (entity, position))
+                .collect(),
+            grab,
+            others,
+            offset: Vector3::zeros(),
+        }
+    }
+
+    /// Follow the pointer at a canvas position, `axis_lock` keeps the move along x or y
+    /// alone. Returns the new positions of the dragged entities.
+    pub fn drag_to(
+        &mut self,
+        camera: &Camera,
+        screen: Vector2<f32>,
+        axis_lock: bool,
+        snap: SnapSettings,
+    ) -> Vec<(usize, Vector3<f32>)> {
+        let plane = Vector3::new(0.0, 0.0, self.grab.z);
+        // the plane seen edge on, or a degenerate camera, keeps the last offset
+        if let Some(pointer) = camera
+            .screen_ray(screen)
+            .and_then(|ray| ray.intersect_plane(plane, Vector3::z()))
+        {
+            let tolerance = SNAP_PIXELS / camera.pixels_per_unit();
+            self.offset = self.snapped_offset(pointer - self.grab, axis_lock, snap, tolerance);
+        }
+        self.positions()
+    }
+
+    pub fn positions(&self) -> Vec<(usize, Vector3<f32>)> {
+        self.entities
+            .iter()
+            .map(|&(entity, from)| (entity, from + self.offset))
+            .collect()
+    }
+
+    /// Moves of the entities that ended up somewhere else.
+    pub fn moves(&self) -> Vec<EntityMove> {
+        self.entities
+            .iter()
+            .map(|&(entity, from)| EntityMove::new(entity, from, from + self.offset))
+            .filter(|entity_move| entity_move.from != entity_move.to)
+            .collect()
+    }
+
+    fn snapped_offset(
+        &self,
+        offset: Vector3<f32>,
+        axis_lock: bool,
+        snap: SnapSettings,
+        tolerance: f32,
+    ) -> Vector3<f32> {
+        let mut offset = Vector3::new(offset.x, offset.y, 0.0);
+        let mut free = [true, true];
+        if axis_lock {
+            let locked = if offset.x.abs() >= offset.y.abs() {
+                1
+            } else {
+                0
+            };
+            offset[locked] = 0.0;
+            free[locked] = false;
+        }
+        // the grid places the grabbed entity, the others keep their distance to it
+        let anchor = self.entities[0].1;
+        for axis in (0..2).filter(|&axis| free[axis]) {
+            if snap.grid > 0.0 {
+                let target = ((anchor[axis] + offset[axis]) / snap.grid).round() * snap.grid;
+                offset[axis] = target - anchor[axis];
+            }
+            // edges win over the grid, lining up is what the user is after when both are near
+            if snap.to_entities {
+                if let Some(snapped) = self.edge_snap(axis, offset[axis], tolerance) {
+                    offset[axis] = snapped;
+                }
+            }
+        }
+        offset
+    }
+
+    /// Offset along `axis` within `tolerance` of `offset` that lines up edges with another entity.
+    fn edge_snap(&self, axis: usize, offset: f32, tolerance: f32) -> Option<f32> {
+        let edges = [self.bounds.min[axis], self.bounds.max[axis]];
+        self.others
+            .iter()
+            .flat_map(|other| [other.min[axis], other.max[axis]])
+            .flat_map(|target| edges.map(|edge| target - edge))
+            .filter(|snapped| (snapped - offset).abs() <= tolerance)
+            .min_by(|a, b| (a - offset).abs().total_cmp(&(b - offset).abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scene;
+    use crate::{process_input, DragMode};
+
+    fn unit_box(x: f32, y: f32) -> Bounds {
+        Bounds {
+            min: Vector3::new(x - 0.5, y - 0.5, 0.0),
+            max: Vector3::new(x + 0.5, y + 0.5, 0.0),
+        }
+    }
+
+    fn drag(others: Vec<Bounds>) -> EntityDrag {
+        EntityDrag::new(
+            vec![
+                (2, Vector3::new(0.0, 0.0, 0.0), unit_box(0.0, 0.0)),
+                (5, Vector3::new(1.0, 0.0, 0.0), unit_box(1.0, 0.0)),
+            ],
+            others,
+            Vector3::zeros(),
+        )
+    }
+
+    #[test]
+    fn grid_snaps_the_grabbed_entity_and_axis_lock_keeps_the_longer_move() {
+        let drag = drag(Vec::new());
+        let grid = SnapSettings {
+            grid: 0.5,
+            to_entities: false,
+        };
+
+        let offset = drag.snapped_offset(Vector3::new(1.3, -0.6, 0.0), false, grid, 0.1);
+        assert_eq!(offset, Vector3::new(1.5, -0.5, 0.0));
+
+        let offset = drag.snapped_offset(Vector3::new(1.3, -2.6, 0.0), true, grid, 0.1);
+        assert_eq!(offset, Vector3::new(0.0, -2.5, 0.0));
+    }
+
+    #[test]
+    fn edges_snap_to_the_nearest_edge_of_another_entity() {
+        let drag = drag(vec![unit_box(4.0, 3.0)]);
+        let edges = SnapSettings {
+            grid: 0.0,
+            to_entities: true,
+        };
+
+        // the right edge of the pair at 1.5 meets the left edge of the other box at 3.5
+        let offset = drag.snapped_offset(Vector3::new(1.9, 0.2, 0.0), false, edges, 0.25);
+        assert_eq!(offset, Vector3::new(2.0, 0.2, 0.0));
+
+        let far = drag.snapped_offset(Vector3::new(1.6, 0.2, 0.0), false, edges, 0.25);
+        assert_eq!(far, Vector3::new(1.6, 0.2, 0.0));
+    }
+
+    #[test]
+    fn dragging_an_entity_moves_the_selection_and_reports_the_move() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_quad(3.0, 0.0, 0.0);
+            options.add_quad(0.0, 3.0, 0.0);
+            options.set_snap_grid(0.5).unwrap();
+        });
+        let camera = renderer.camera.clone();
+        let screen = |x, y| camera.world_to_screen(Vector3::new(x, y, 0.0));
+        renderer.select(&[1]);
+
+        renderer.pointer_down(1, screen(0.2, 0.1), DragMode::Orbit, true);
+        renderer.pointer_move(1, screen(0.6, 0.1));
+        process_input(&mut renderer, 0.0);
+        renderer.pointer_move(1, screen(1.3, 2.1));
+        process_input(&mut renderer, 16.0);
+
+        assert_eq!(renderer.selection.entities(), &[1, 0]);
+        assert_eq!(renderer.entities[0].position, Vector3::new(1.0, 2.0, 0.0));
+        assert_eq!(renderer.entities[1].position, Vector3::new(4.0, 2.0, 0.0));
+        assert_eq!(renderer.entities[2].position, Vector3::new(0.0, 3.0, 0.0));
+        assert_eq!(renderer.camera, camera);
+
+        renderer.axis_lock = true;
+        renderer.pointer_move(1, screen(1.3, 0.8));
+        process_input(&mut renderer, 32.0);
+        assert_eq!(renderer.entities[0].position, Vector3::new(1.0, 0.0, 0.0));
+        assert!(renderer.take_finished_moves().is_empty());
+
+        renderer.pointer_up(1);
+        let moves = renderer.take_finished_moves();
+        assert_eq!(moves.len(), 2);
+        assert_eq!((moves[0].entity, moves[0].from()), (0, vec![0.0, 0.0, 0.0]));
+        assert_eq!(moves[0].to(), vec![1.0, 0.0, 0.0]);
+        assert_eq!(moves[1].to(), vec![4.0, 0.0, 0.0]);
+        assert!(!renderer.clicked);
+    }
+}
@@ -0,0 +1,155 @@
+use js_sys::Reflect;
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::JsValue;
+
+/// Everything that can go wrong while creating or drawing a scene.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RendererError {
+    /// The options passed from JS could not be understood.
+    InvalidOptions(String),
+    CanvasNotFound(String),
+    ContextCreation(String),
+    /// A GL object could not be created, usually because the context is lost.
+    ResourceCreation(&'static str),
+    ShaderCompile {
+        stage: ShaderStage,
+        log: String,
+    },
+    ProgramLink(String),
+    MissingUniform(String),
+    TextureLoad(String),
+    /// The feature needs WebGL2 or an extension the context does not have.
+    Unsupported(&'static str),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+/// Called with errors that happen after [`crate::run`] returned, like a texture failing to load
+/// or a draw call failing in the update loop.
+pub type ErrorHandler = Rc<dyn Fn(RendererError)>;
+
+impl RendererError {
+    /// Stable identifier exposed to JS as the `kind` property of the error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RendererError::InvalidOptions(_) => "invalidOptions",
+            RendererError::CanvasNotFound(_) => "canvasNotFound",
+            RendererError::ContextCreation(_) => "contextCreation",
+            RendererError::ResourceCreation(_) => "resourceCreation",
+            RendererError::ShaderCompile { .. } => "shaderCompile",
+            RendererError::ProgramLink(_) => "programLink",
+            RendererError::MissingUniform(_) => "missingUniform",
+            RendererError::TextureLoad(_) => "textureLoad",
+            RendererError::Unsupported(_) => "unsupported",
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::InvalidOptions(message) => write!(f, "invalid options: {}", message),
+            RendererError::CanvasNotFound(id) => write!(f, "no canvas element with id #{}", id),
+            RendererError::ContextCreation(message) => {
+                write!(f, "unable to initialize WebGL: {}", message)
+            }
+            RendererError::ResourceCreation(resource) => write!(f, "failed to create {}", resource),
+            RendererError::ShaderCompile { stage, log } => {
+                write!(
+                    f,
+                    "an error occurred compiling the {} shader: {}",
+                    stage, log
+                )
+            }
+            RendererError::ProgramLink(log) => {
+                write!(f, "an error occurred linking the shaders: {}", log)
+            }
+            RendererError::MissingUniform(name) => write!(f, "can't get {} location", name),
+            RendererError::TextureLoad(url) => write!(f, "failed to load texture {}", url),
+            RendererError::Unsupported(feature) => {
+                write!(f, "{} are not supported by this context", feature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<RendererError> for JsValue {
+    fn from(error: RendererError) -> JsValue {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name("RendererError");
+        // setting a property on a fresh error object can't fail
+        let _ = Reflect::set(&js_error, &"kind".into(), &error.kind().into());
+        js_error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Call, RecordingBackend};
+    use crate::handle::report_errors;
+    use crate::tests::renderer;
+    use crate::{draw_scene, Renderer, RendererOptions};
+    use std::cell::RefCell;
+
+    #[test]
+    fn missing_uniform_stops_the_frame_with_an_error() {
+        let backend = RecordingBackend::new();
+        backend.remove_uniform("uNormalMatrix");
+        let mut renderer = renderer(&backend, &RendererOptions::default());
+        backend.clear_calls();
+
+        let result = draw_scene(&mut renderer);
+
+        assert_eq!(
+            result,
+            Err(RendererError::MissingUniform("uNormalMatrix".to_string()))
+        );
+        assert!(!backend
+            .calls()
+            .iter()
+            .any(|call| matches!(call, Call::DrawTriangles(_))));
+    }
+
+    #[test]
+    fn repeated_errors_are_reported_once() {
+        let reported = Rc::new(std::cell::Cell::new(0));
+        let counter = reported.clone();
+        let backend = RecordingBackend::new();
+        let mut renderer = Renderer::new(
+            Box::new(backend),
+            &RendererOptions::default(),
+            Rc::new(move |_| counter.set(counter.get() + 1)),
+            800,
+            600,
+        )
+        .unwrap();
+
+        renderer.report(RendererError::MissingUniform("uSampler".to_string()));
+        renderer.report(RendererError::MissingUniform("uSampler".to_string()));
+        renderer.report(RendererError::TextureLoad("logo.png".to_string()));
+        // the handler only runs once the renderer is released
+        assert_eq!(reported.get(), 0);
+
+        let renderer = RefCell::new(renderer);
+        report_errors(&renderer);
+        report_errors(&renderer);
+        assert_eq!(reported.get(), 2);
+    }
+}
@@ -0,0 +1,745 @@
+use crate::actions::key_name;
+use crate::options::{check_zoom_limits, EntityDescription, EntityKind};
+use crate::wheel::WheelDelta;
+use crate::{
+    get_mouse_position, update, Action, DragMode, FrameStats, Hit, Projection, RenderMode,
+    Renderer, RendererError, WorldRect,
+};
+use gloo::events::{EventListener, EventListenerOptions};
+use gloo::render::request_animation_frame;
+use js_sys::{Array, Function};
+use nalgebra::{Vector2, Vector3};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    FocusOptions, HtmlCanvasElement, KeyboardEvent, MouseEvent, PointerEvent, ResizeObserver,
+    WheelEvent,
+};
+
+/// Owns a [`Renderer`] together with its event listeners and animation frame.
+///
+/// Each handle drives its own canvas, so several of them can live on the same page. Calling
+/// [`RendererHandle::destroy`] (or freeing the handle from JS) unregisters the listeners, stops
+/// the update loop and releases the GL resources.
+#[wasm_bindgen]
+pub struct RendererHandle {
+    renderer: Option<Rc<RefCell<Renderer>>>,
+    frame_requester: FrameRequester,
+    callbacks: Rc<HostCallbacks>,
+    listeners: Vec<EventListener>,
+    resize_observer: Option<(ResizeObserver, Closure<dyn FnMut()>)>,
+}
+
+#[wasm_bindgen]
+impl RendererHandle {
+    /// Stop rendering and release everything owned by this handle. Calling it twice is a no-op.
+    pub fn destroy(&mut self) {
+        // dropping the listeners unregisters them from the document
+        self.listeners.clear();
+        if let Some((resize_observer, _)) = self.resize_observer.take() {
+            resize_observer.disconnect();
+        }
+        if let Some(renderer) = self.renderer.take() {
+            let mut renderer = renderer.borrow_mut();
+            renderer.animation_handler = None;
+            renderer.overlay = None;
+            renderer.free();
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn destroyed(&self) -> bool {
+        self.renderer.is_none()
+    }
+
+    /// Whether the browser dropped the GL context, rendering resumes once it is restored.
+    #[wasm_bindgen(getter)]
+    pub fn context_lost(&self) -> bool {
+        match &self.renderer {
+            Some(renderer) => renderer.borrow().backend.is_context_lost(),
+            None => false,
+        }
+    }
+
+    /// Statistics of the recent frames, empty once destroyed.
+    pub fn stats(&self) -> FrameStats {
+        match &self.renderer {
+            Some(renderer) => renderer.borrow().stats,
+            None => Default::default(),
+        }
+    }
+
+    /// Draw a new frame, for changes the renderer can't see by itself.
+    pub fn request_redraw(&self) {
+        self.frame_requester.request_frame();
+    }
+
+    /// Position in CSS pixels from the top left corner of the canvas where the world position
+    /// is drawn, as `[x, y]`. Meant to place HTML elements over scene nodes.
+    pub fn world_to_screen(&self, x: f32, y: f32, z: f32) -> Option<Vec<f32>> {
+        let renderer = self.renderer.as_ref()?.borrow();
+        let screen = renderer.camera.world_to_screen(Vector3::new(x, y, z));
+        Some(vec![screen.x, screen.y])
+    }
+
+    /// World position on the z = 0 plane under a canvas position, as `[x, y, z]`.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Option<Vec<f32>> {
+        let renderer = self.renderer.as_ref()?.borrow();
+        let world = renderer.camera.screen_to_world(Vector2::new(x, y));
+        Some(vec![world.x, world.y, world.z])
+    }
+
+    /// Topmost entity drawn at a canvas position.
+    pub fn pick(&self, x: f32, y: f32) -> Option<Hit> {
+        self.renderer
+            .as_ref()?
+            .borrow_mut()
+            .pick(Vector2::new(x, y))
+    }
+
+    /// Part of the scene currently on screen.
+    pub fn visible_rect(&self) -> Option<WorldRect> {
+        Some(self.renderer.as_ref()?.borrow().camera.visible_rect())
+    }
+
+    /// Size in CSS pixels of one world unit.
+    pub fn pixels_per_unit(&self) -> Option<f32> {
+        Some(self.renderer.as_ref()?.borrow().camera.pixels_per_unit())
+    }
+
+    /// Run `action` when `key`, a `KeyboardEvent.key` value after its `Ctrl+`, `Alt+` or
+    /// `Shift+` modifiers like `Ctrl+Shift+z`, is pressed on the canvas.
+    pub fn bind_key(&self, key: String, action: Action) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().bindings.bind(key, action);
+        }
+    }
+
+    pub fn unbind_key(&self, key: &str) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().bindings.unbind(key);
+        }
+    }
+
+    /// Index in the scene of the entity selected last.
+    #[wasm_bindgen(getter)]
+    pub fn selected_entity(&self) -> Option<usize> {
+        self.renderer.as_ref()?.borrow().selection.primary()
+    }
+
+    /// Indices in the scene of the selected entities, in the order they were selected.
+    #[wasm_bindgen(getter)]
+    pub fn selected_entities(&self) -> Vec<usize> {
+        match &self.renderer {
+            Some(renderer) => renderer.borrow().selection.entities().to_vec(),
+            None => vec![],
+        }
+    }
+
+    /// Select these entities alone, indices out of the scene are skipped.
+    pub fn select(&self, entities: Vec<usize>) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().select(&entities);
+            report_selection(renderer, &self.callbacks);
+        }
+    }
+
+    pub fn clear_selection(&self) {
+        self.select(vec![]);
+    }
+
+    /// Put a quad in the scene and select it, returns its index. Undo removes it again.
+    pub fn add_quad(&self, x: f32, y: f32, z: f32) -> Result<Option<usize>, RendererError> {
+        self.add_entity(EntityKind::Quad, x, y, z)
+    }
+
+    pub fn add_cube(&self, x: f32, y: f32, z: f32) -> Result<Option<usize>, RendererError> {
+        self.add_entity(EntityKind::Cube, x, y, z)
+    }
+
+    pub fn add_text(
+        &self,
+        text: String,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> Result<Option<usize>, RendererError> {
+        self.add_entity(EntityKind::Text(text), x, y, z)
+    }
+
+    /// Show `text` on `entity` instead of its current mesh. Undo brings the old one back.
+    pub fn set_text(&self, entity: usize, text: &str) -> Result<(), RendererError> {
+        if let Some(renderer) = &self.renderer {
+            let result = renderer.borrow_mut().set_text(entity, text);
+            report_changes(renderer, &self.callbacks);
+            self.frame_requester.request_frame();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Revert the last edit of the scene, like ctrl+z.
+    pub fn undo(&self) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().undo();
+            report_changes(renderer, &self.callbacks);
+            self.frame_requester.request_frame();
+        }
+    }
+
+    /// Apply the last undone edit again, like ctrl+shift+z.
+    pub fn redo(&self) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().redo();
+            report_changes(renderer, &self.callbacks);
+            self.frame_requester.request_frame();
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn can_undo(&self) -> bool {
+        match &self.renderer {
+            Some(renderer) => renderer.borrow().history.can_undo(),
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn can_redo(&self) -> bool {
+        match &self.renderer {
+            Some(renderer) => renderer.borrow().history.can_redo(),
+            None => false,
+        }
+    }
+
+    /// Animate the camera until `rect` fills the canvas, `duration` is in milliseconds.
+    pub fn fly_to(&self, rect: WorldRect, duration: f64) {
+        if let Some(renderer) = &self.renderer {
+            let mut renderer = renderer.borrow_mut();
+            let renderer = &mut *renderer;
+            renderer
+                .camera_motion
+                .fly_to(&renderer.camera, rect, (duration * 0.001) as f32);
+        }
+        self.frame_requester.request_frame();
+    }
+
+    /// Change how far the camera can zoom, the current zoom is brought within the limits.
+    pub fn set_zoom_limits(&self, min: f32, max: f32) -> Result<(), RendererError> {
+        check_zoom_limits(min, max)?;
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().camera.set_zoom_limits(min, max);
+        }
+        self.frame_requester.request_frame();
+        Ok(())
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_projection(&self, projection: Projection) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().camera.set_projection(projection);
+        }
+        self.frame_requester.request_frame();
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_render_mode(&self, render_mode: RenderMode) {
+        if let Some(renderer) = &self.renderer {
+            renderer.borrow_mut().render_mode = render_mode;
+        }
+        self.frame_requester.request_frame();
+    }
+}
+
+impl RendererHandle {
+    pub(crate) fn new(
+        renderer: Renderer,
+        canvas: HtmlCanvasElement,
+        frame_requester: FrameRequester,
+        callbacks: HostCallbacks,
+    ) -> RendererHandle {
+        let renderer = Rc::new(RefCell::new(renderer));
+        // touches move the camera instead of scrolling or zooming the page
+        let _ = canvas.style().set_property("touch-action", "none");
+        // focusable, so that it can take keyboard input
+        if !canvas.has_attribute("tabindex") {
+            canvas.set_tab_index(0);
+        }
+        let callbacks = Rc::new(callbacks);
+        frame_requester.attach(Rc::downgrade(&renderer), canvas.clone(), callbacks.clone());
+        let listeners = register_listeners(&renderer, &canvas, &frame_requester, &callbacks);
+        let resize_observer = observe_resize(&canvas, &frame_requester);
+        frame_requester.request_frame();
+        RendererHandle {
+            renderer: Some(renderer),
+            frame_requester,
+            callbacks,
+            listeners,
+            resize_observer,
+        }
+    }
+
+    fn add_entity(
+        &self,
+        kind: EntityKind,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> Result<Option<usize>, RendererError> {
+        let renderer = match &self.renderer {
+            Some(renderer) => renderer,
+            None => return Ok(None),
+        };
+        let description = EntityDescription {
+            kind,
+            position: Vector3::new(x, y, z),
+        };
+        let result = renderer.borrow_mut().add_entity(&description);
+        report_changes(renderer, &self.callbacks);
+        self.frame_requester.request_frame();
+        result.map(Some)
+    }
+}
+
+impl Drop for RendererHandle {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+/// Callbacks of the host page, told about what happens in the scene.
+#[derive(Clone, Default)]
+pub(crate) struct HostCallbacks {
+    pub on_click: Option<Function>,
+    pub on_double_click: Option<Function>,
+    pub on_hover: Option<Function>,
+    pub on_selection_change: Option<Function>,
+    pub on_move_end: Option<Function>,
+    pub on_history_change: Option<Function>,
+}
+
+/// Call `callback` with the hit, or null.
+fn notify(callback: &Function, hit: Option<Hit>) {
+    let hit = hit.map_or(JsValue::NULL, JsValue::from);
+    // the host callback throwing must not take the listener down with it
+    let _ = callback.call1(&JsValue::NULL, &hit);
+}
+
+fn report_selection(renderer: &RefCell<Renderer>, callbacks: &HostCallbacks) {
+    let callback = match &callbacks.on_selection_change {
+        Some(callback) => callback,
+        None => return,
+    };
+    let change = renderer.borrow_mut().take_selection_change();
+    if let Some(entities) = change {
+        let entities: Array = entities
+            .into_iter()
+            .map(|entity| JsValue::from(entity as u32))
+            .collect();
+        let _ = callback.call1(&JsValue::NULL, &entities);
+    }
+}
+
+fn report_moves(renderer: &RefCell<Renderer>, callbacks: &HostCallbacks) {
+    let moves = renderer.borrow_mut().take_finished_moves();
+    let callback = match &callbacks.on_move_end {
+        Some(callback) if !moves.is_empty() => callback,
+        _ => return,
+    };
+    let moves: Array = moves.into_iter().map(JsValue::from).collect();
+    let _ = callback.call1(&JsValue::NULL, &moves);
+}
+
+fn report_history(renderer: &RefCell<Renderer>, callbacks: &HostCallbacks) {
+    let callback = match &callbacks.on_history_change {
+        Some(callback) => callback,
+        None => return,
+    };
+    let change = renderer.borrow_mut().take_history_change();
+    if let Some((can_undo, can_redo)) = change {
+        let _ = callback.call2(
+            &JsValue::NULL,
+            &JsValue::from(can_undo),
+            &JsValue::from(can_redo),
+        );
+    }
+}
+
+pub(crate) fn report_errors(renderer: &RefCell<Renderer>) {
+    let (errors, error_handler) = renderer.borrow_mut().take_errors();
+    for error in errors {
+        error_handler(error);
+    }
+}
+
+/// Everything the host page hears about after input or a call changed the scene, called once
+/// the renderer is no longer borrowed since the host may call back into the handle.
+fn report_changes(renderer: &RefCell<Renderer>, callbacks: &HostCallbacks) {
+    report_errors(renderer);
+    report_selection(renderer, callbacks);
+    report_moves(renderer, callbacks);
+    report_history(renderer, callbacks);
+}
+
+/// Schedules the next animation frame for everything that can change the scene outside of the
+/// update loop: input, resizes, loaded textures or the host page.
+#[derive(Clone, Default)]
+pub(crate) struct FrameRequester {
+    target: Rc<RefCell<Option<FrameTarget>>>,
+}
+
+struct FrameTarget {
+    renderer: Weak<RefCell<Renderer>>,
+    canvas: HtmlCanvasElement,
+    callbacks: Rc<HostCallbacks>,
+}
+
+impl FrameRequester {
+    fn attach(
+        &self,
+        renderer: Weak<RefCell<Renderer>>,
+        canvas: HtmlCanvasElement,
+        callbacks: Rc<HostCallbacks>,
+    ) {
+        *self.target.borrow_mut() = Some(FrameTarget {
+            renderer,
+            canvas,
+            callbacks,
+        });
+    }
+
+    /// Mark the scene as changed and make sure a frame is coming, at most one is scheduled.
+    pub(crate) fn request_frame(&self) {
+        let (renderer, canvas, callbacks) = match &*self.target.borrow() {
+            Some(target) => (
+                target.renderer.clone(),
+                target.canvas.clone(),
+                target.callbacks.clone(),
+            ),
+            None => return,
+        };
+        if let Some(strong) = renderer.upgrade() {
+            let mut strong = strong.borrow_mut();
+            strong.request_redraw();
+            if strong.animation_handler.is_some() || strong.backend.is_context_lost() {
+                return;
+            }
+        }
+        schedule_update(renderer, canvas, callbacks);
+    }
+}
+
+fn register_listeners(
+    renderer: &Rc<RefCell<Renderer>>,
+    canvas: &HtmlCanvasElement,
+    frame_requester: &FrameRequester,
+    callbacks: &HostCallbacks,
+) -> Vec<EventListener> {
+    let mut listeners = Vec::new();
+
+    // with the pointer captured on a drag, moves and releases outside of the canvas still
+    // arrive here
+    listeners.push(EventListener::new(canvas, "pointermove", {
+        let renderer = Rc::downgrade(renderer);
+        let canvas = canvas.clone();
+        let frame_requester = frame_requester.clone();
+        let on_hover = callbacks.on_hover.clone();
+        move |event| {
+            let event = event.unchecked_ref::<PointerEvent>();
+            let position = get_mouse_position(&canvas, event);
+            let (pressed, hovered) = match renderer.upgrade() {
+                Some(renderer) => {
+                    let moved = {
+                        let mut renderer = renderer.borrow_mut();
+                        renderer.axis_lock = event.shift_key();
+                        let pressed = renderer.pointer_move(event.pointer_id(), position);
+                        // picking on every move is only worth it when someone listens
+                        let hovered = match &on_hover {
+                            Some(_) if !pressed => renderer.hover(Some(position)),
+                            _ => None,
+                        };
+                        (pressed, hovered)
+                    };
+                    report_errors(&renderer);
+                    moved
+                }
+                None => (false, None),
+            };
+            // hovering does not change anything, dragging moves the camera
+            if pressed {
+                frame_requester.request_frame();
+            }
+            if let (Some(on_hover), Some(hit)) = (&on_hover, hovered) {
+                notify(on_hover, hit);
+            }
+        }
+    }));
+
+    if let Some(on_hover) = callbacks.on_hover.clone() {
+        listeners.push(EventListener::new(canvas, "pointerleave", {
+            let renderer = Rc::downgrade(renderer);
+            move |_event| {
+                let hovered = match renderer.upgrade() {
+                    Some(renderer) => {
+                        let hovered = renderer.borrow_mut().hover(None);
+                        report_errors(&renderer);
+                        hovered
+                    }
+                    None => None,
+                };
+                if let Some(hit) = hovered {
+                    notify(&on_hover, hit);
+                }
+            }
+        }));
+    }
+
+    // the browser sends clicks after drags too, only pointers released without dragging count
+    let clicks = [
+        ("click", callbacks.on_click.clone()),
+        ("dblclick", callbacks.on_double_click.clone()),
+    ];
+    for (name, callback) in clicks {
+        let callback = match callback {
+            Some(callback) => callback,
+            None => continue,
+        };
+        listeners.push(EventListener::new(canvas, name, {
+            let renderer = Rc::downgrade(renderer);
+            let canvas = canvas.clone();
+            move |event| {
+                let event = event.unchecked_ref::<MouseEvent>();
+                let hit = match renderer.upgrade() {
+                    Some(renderer) => {
+                        let hit = {
+                            let mut renderer = renderer.borrow_mut();
+                            if !renderer.clicked {
+                                return;
+                            }
+                            renderer.pick(get_mouse_position(&canvas, event))
+                        };
+                        report_errors(&renderer);
+                        hit
+                    }
+                    None => return,
+                };
+                notify(&callback, hit);
+            }
+        }));
+    }
+
+    listeners.push(EventListener::new(canvas, "pointerdown", {
+        let renderer = Rc::downgrade(renderer);
+        let canvas = canvas.clone();
+        move |event| {
+            let event = event.unchecked_ref::<PointerEvent>();
+            if let Some(renderer) = renderer.upgrade() {
+                // the main mouse button orbits a perspective camera, touches or another button
+                // pan
+                let drag_mode = if event.pointer_type() == "mouse" && event.button() == 0 {
+                    DragMode::Orbit
+                } else {
+                    DragMode::Pan
+                };
+                // shift or ctrl clicks toggle the selection and drags from empty space select,
+                // shift also keeps dragged entities on one axis
+                let toggle = event.shift_key() || event.ctrl_key() || event.meta_key();
+                let position = get_mouse_position(&canvas, event);
+                {
+                    let mut renderer = renderer.borrow_mut();
+                    renderer.axis_lock = event.shift_key();
+                    renderer.pointer_down(event.pointer_id(), position, drag_mode, toggle);
+                }
+                report_errors(&renderer);
+            }
+            let _ = canvas.set_pointer_capture(event.pointer_id());
+            // keyboard input goes to the canvas that was last used
+            let options = FocusOptions::new();
+            options.set_prevent_scroll(true);
+            let _ = canvas.focus_with_options(&options);
+        }
+    }));
+
+    // losing the capture, like when the canvas leaves the page, ends the drag too
+    for name in ["pointerup", "pointercancel", "lostpointercapture"] {
+        listeners.push(EventListener::new(canvas, name, {
+            let renderer = Rc::downgrade(renderer);
+            let frame_requester = frame_requester.clone();
+            move |event| {
+                let event = event.unchecked_ref::<PointerEvent>();
+                let released = match renderer.upgrade() {
+                    Some(renderer) => renderer.borrow_mut().pointer_up(event.pointer_id()),
+                    None => false,
+                };
+                // the next frame lets the camera glide on, and reports the finished drags
+                if released {
+                    frame_requester.request_frame();
+                }
+            }
+        }));
+    }
+
+    // the page must neither scroll nor zoom, ctrl+wheel is how trackpads pinch
+    listeners.push(EventListener::new_with_options(
+        canvas,
+        "wheel",
+        EventListenerOptions::enable_prevent_default(),
+        {
+            let renderer = Rc::downgrade(renderer);
+            let canvas = canvas.clone();
+            let frame_requester = frame_requester.clone();
+            move |event| {
+                event.prevent_default();
+                let event = event.unchecked_ref::<WheelEvent>();
+                if let Some(renderer) = renderer.upgrade() {
+                    let mut renderer = renderer.borrow_mut();
+                    let delta = WheelDelta {
+                        x: event.delta_x(),
+                        y: event.delta_y(),
+                        mode: event.delta_mode(),
+                        ctrl_key: event.ctrl_key(),
+                    };
+                    let settings = renderer.wheel_settings;
+                    renderer
+                        .wheel_input
+                        .add(delta, canvas.client_height() as f64, &settings);
+                }
+                frame_requester.request_frame();
+            }
+        },
+    ));
+
+    listeners.push(EventListener::new_with_options(
+        canvas,
+        "keydown",
+        EventListenerOptions::enable_prevent_default(),
+        {
+            let renderer = Rc::downgrade(renderer);
+            let frame_requester = frame_requester.clone();
+            move |event| {
+                let event = event.unchecked_ref::<KeyboardEvent>();
+                // cmd on macOS plays the part of ctrl
+                let key = key_name(
+                    &event.key(),
+                    event.ctrl_key() || event.meta_key(),
+                    event.alt_key(),
+                    event.shift_key(),
+                );
+                let bound = match renderer.upgrade() {
+                    Some(renderer) => renderer.borrow_mut().key_down(&key, event.repeat()),
+                    None => false,
+                };
+                // the browser and page shortcuts that are not bound are left alone
+                if bound {
+                    // arrows would scroll the page, ctrl+z would undo in the page
+                    event.prevent_default();
+                    frame_requester.request_frame();
+                }
+            }
+        },
+    ));
+
+    listeners.push(EventListener::new(canvas, "keyup", {
+        let renderer = Rc::downgrade(renderer);
+        move |event| {
+            let event = event.unchecked_ref::<KeyboardEvent>();
+            if let Some(renderer) = renderer.upgrade() {
+                renderer.borrow_mut().keyboard.key_up(&event.key());
+            }
+        }
+    }));
+
+    // keys released elsewhere never send their keyup here
+    listeners.push(EventListener::new(canvas, "blur", {
+        let renderer = Rc::downgrade(renderer);
+        move |_event| {
+            if let Some(renderer) = renderer.upgrade() {
+                renderer.borrow_mut().keyboard.release_all();
+            }
+        }
+    }));
+
+    // the right button pans
+    listeners.push(EventListener::new_with_options(
+        canvas,
+        "contextmenu",
+        EventListenerOptions::enable_prevent_default(),
+        |event| event.prevent_default(),
+    ));
+
+    // without preventDefault the browser never restores the context
+    listeners.push(EventListener::new_with_options(
+        canvas,
+        "webglcontextlost",
+        EventListenerOptions::enable_prevent_default(),
+        {
+            let renderer = Rc::downgrade(renderer);
+            move |event| {
+                event.prevent_default();
+                if let Some(renderer) = renderer.upgrade() {
+                    renderer.borrow_mut().animation_handler = None;
+                }
+            }
+        },
+    ));
+
+    listeners.push(EventListener::new(canvas, "webglcontextrestored", {
+        let renderer = Rc::downgrade(renderer);
+        let frame_requester = frame_requester.clone();
+        move |_event| {
+            if let Some(renderer) = renderer.upgrade() {
+                let restored = renderer.borrow_mut().restore_context();
+                if let Err(error) = restored {
+                    renderer.borrow_mut().report(error);
+                    report_errors(&renderer);
+                    return;
+                }
+            }
+            frame_requester.request_frame();
+        }
+    }));
+
+    listeners
+}
+
+/// Redraw when the canvas changes size, its drawing buffer has to follow.
+fn observe_resize(
+    canvas: &HtmlCanvasElement,
+    frame_requester: &FrameRequester,
+) -> Option<(ResizeObserver, Closure<dyn FnMut()>)> {
+    let on_resize = Closure::<dyn FnMut()>::new({
+        let frame_requester = frame_requester.clone();
+        move || frame_requester.request_frame()
+    });
+    // older browsers without ResizeObserver only pick up new sizes with the next change
+    let resize_observer = ResizeObserver::new(on_resize.as_ref().unchecked_ref()).ok()?;
+    resize_observer.observe(canvas);
+    Some((resize_observer, on_resize))
+}
+
+/// The callback only holds a weak reference, so the loop stops once the handle is destroyed.
+fn schedule_update(
+    renderer: Weak<RefCell<Renderer>>,
+    canvas: HtmlCanvasElement,
+    callbacks: Rc<HostCallbacks>,
+) {
+    let strong = match renderer.upgrade() {
+        Some(strong) => strong,
+        None => return,
+    };
+    let animation_handler = request_animation_frame(move |timestamp| {
+        if let Some(strong) = renderer.upgrade() {
+            let keep_running = update(&mut strong.borrow_mut(), &canvas, timestamp);
+            if keep_running {
+                schedule_update(renderer, canvas, callbacks.clone());
+            } else {
+                strong.borrow_mut().animation_handler = None;
+            }
+            // clicks, drags and keys change the scene as the frame applies them
+            report_changes(&strong, &callbacks);
+        }
+    });
+    strong.borrow_mut().animation_handler = Some(animation_handler);
+}
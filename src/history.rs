@@ -0,0 +1,392 @@
+use crate::backend::Backend;
+use crate::entity_drag::EntityMove;
+use crate::mesh::Mesh;
+use crate::primitive::Primitive;
+
+/// Commands kept to undo, the oldest ones are dropped beyond it.
+const HISTORY_LIMIT: usize = 100;
+
+/// Invertible edit of the scene. Commands are recorded once done.
+pub(crate) enum Command {
+    /// Entities put somewhere else.
+    Move(Vec<EntityMove>),
+    /// Entities removed by ascending index, held with their GPU resources while deleted.
+    Delete {
+        indices: Vec<usize>,
+        removed: Vec<Primitive>,
+    },
+    /// Entity put in the scene at `index`, held while the addition is undone.
+    Add {
+        index: usize,
+        removed: Option<Primitive>,
+    },
+    /// Mesh of `entity` replaced, the command holds the other one and swaps them.
+    Retext { entity: usize, mesh: Mesh },
+}
+
+impl Command {
+    /// Do the command again.
+    pub fn apply(&mut self, entities: &mut Vec<Primitive>) -> Edit {
+        match self {
+            Command::Move(moves) => {
+                for entity_move in moves.iter() {
+                    entities[entity_move.entity].move_to(entity_move.to);
+                }
+                Edit::moved(moves.clone())
+            }
+            Command::Delete { indices, removed } => {
+                // from the last one, so that the indices left to remove stay valid
+                for &index in indices.iter().rev() {
+                    removed.push(entities.remove(index));
+                }
+                removed.reverse();
+                // the entity that took the place of the first one
+                let selection = match (indices.first(), entities.len()) {
+                    (Some(&first), len) if len > 0 => vec![first.min(len - 1)],
+                    _ => Vec::new(),
+                };
+                Edit {
+                    selection,
+                    moves: Vec::new(),
+                }
+            }
+            Command::Add { index, removed } => {
+                if let Some(entity) = removed.take() {
+                    entities.insert(*index, entity);
+                }
+                Edit::selected(vec![*index])
+            }
+            Command::Retext { entity, mesh } => {
+                entities[*entity].swap_mesh(mesh);
+                Edit::selected(vec![*entity])
+            }
+        }
+    }
+
+    /// Undo the command.
+    pub fn revert(&mut self, entities: &mut Vec<Primitive>) -> Edit {
+        match self {
+            Command::Move(moves) => {
+                for entity_move in moves.iter() {
+                    entities[entity_move.entity].move_to(entity_move.from);
+                }
+                Edit::moved(moves.iter().map(EntityMove::reversed).collect())
+            }
+            Command::Delete { indices, removed } => {
+                for (&index, entity) in indices.iter().zip(removed.drain(..)) {
+                    entities.insert(index, entity);
+                }
+                Edit::selected(indices.clone())
+            }
+            Command::Add { index, removed } => {
+                *removed = Some(entities.remove(*index));
+                Edit::selected(Vec::new())
+            }
+            Command::Retext { entity, mesh } => {
+                entities[*entity].swap_mesh(mesh);
+                Edit::selected(vec![*entity])
+            }
+        }
+    }
+
+    /// Fold `next`, which follows this command, into it. Only moves of the same entities merge.
+    fn merge(&mut self, next: &Command) -> bool {
+        match (self, next) {
+            (Command::Move(moves), Command::Move(next)) => {
+                let same_entities = moves.len() == next.len()
+                    && moves
+                        .iter()
+                        .zip(next)
+                        .all(|(entity_move, next)| entity_move.entity == next.entity);
+                if same_entities {
+                    for (entity_move, next) in moves.iter_mut().zip(next) {
+                        entity_move.to = next.to;
+                    }
+                }
+                same_entities
+            }
+            _ => false,
+        }
+    }
+
+    /// Release the entities held by a command that can no longer be undone or redone.
+    fn discard(self, backend: &dyn Backend) {
+        match self {
+            Command::Delete { removed, .. } => {
+                for entity in removed {
+                    entity.delete_mesh(backend);
+                }
+            }
+            Command::Add {
+                removed: Some(entity),
+                ..
+            } => entity.delete_mesh(backend),
+            Command::Retext { mesh, .. } => mesh.delete(backend),
+            Command::Move(_) | Command::Add { .. } => {}
+        }
+    }
+}
+
+/// What applying or reverting a command changed.
+pub(crate) struct Edit {
+    /// Entities to select, to show what changed.
+    pub selection: Vec<usize>,
+    /// Entities that moved, the host page hears about them like about dragged ones.
+    pub moves: Vec<EntityMove>,
+}
+
+impl Edit {
+    fn selected(selection: Vec<usize>) -> Edit {
+        Edit {
+            selection,
+            moves: Vec::new(),
+        }
+    }
+
+    fn moved(moves: Vec<EntityMove>) -> Edit {
+        Edit {
+            selection: moves.iter().map(|entity_move| entity_move.entity).collect(),
+            moves,
+        }
+    }
+}
+
+/// Done commands to undo, and undone ones to redo until a new command is recorded.
+#[derive(Default)]
+pub(crate) struct History {
+    done: Vec<Command>,
+    undone: Vec<Command>,
+    /// The last command takes the next merged ones, like the steps of a running drag.
+    open: bool,
+    /// Whether undo and redo were possible as last given to the host page.
+    reported: (bool, bool),
+}
+
+impl History {
+    /// Record a command that was just done, `merge` folds it into a last one merged as well.
+    pub fn record(&mut self, backend: &dyn Backend, command: Command, merge: bool) {
+        for undone in self.undone.drain(..) {
+            undone.discard(backend);
+        }
+        let merged = merge
+            && self.open
+            && match self.done.last_mut() {
+                Some(last) => last.merge(&command),
+                None => false,
+            };
+        if !merged {
+            self.done.push(command);
+            if self.done.len() > HISTORY_LIMIT {
+                self.done.remove(0).discard(backend);
+            }
+        }
+        self.open = merge;
+    }
+
+    /// The next merged command starts a new one.
+    pub fn seal(&mut self) {
+        self.open = false;
+    }
+
+    /// `None` when there is nothing to undo.
+    pub fn undo(&mut self, entities: &mut Vec<Primitive>) -> Option<Edit> {
+        let mut command = self.done.pop()?;
+        self.open = false;
+        let edit = command.revert(entities);
+        self.undone.push(command);
+        Some(edit)
+    }
+
+    /// `None` when there is nothing to redo.
+    pub fn redo(&mut self, entities: &mut Vec<Primitive>) -> Option<Edit> {
+        let mut command = self.undone.pop()?;
+        self.open = false;
+        let edit = command.apply(entities);
+        self.done.push(command);
+        Some(edit)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Whether undo and redo are possible, when that changed since it was last taken.
+    pub fn take_change(&mut self) -> Option<(bool, bool)> {
+        let state = (self.can_undo(), self.can_redo());
+        if state == self.reported {
+            return None;
+        }
+        self.reported = state;
+        Some(state)
+    }
+
+    pub fn clear(&mut self, backend: &dyn Backend) {
+        for command in self.done.drain(..).chain(self.undone.drain(..)) {
+            command.discard(backend);
+        }
+        self.open = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actions::key_name;
+    use crate::options::{EntityDescription, EntityKind};
+    use crate::tests::{positions, scene};
+    use crate::{process_input, DragMode, Renderer};
+    use nalgebra::Vector3;
+
+    #[test]
+    fn delete_removes_the_selected_entity() {
+        let (backend, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(1.0, 0.0, 0.0);
+            options.add_quad(2.0, 0.0, 0.0);
+        });
+        let buffers = backend.live_buffer_count();
+
+        renderer.key_down("[", false);
+        renderer.key_down("[", true);
+        renderer.key_down("Delete", false);
+        process_input(&mut renderer, 0.0);
+
+        assert_eq!(positions(&renderer), vec![0.0, 2.0]);
+        assert_eq!(renderer.selection.entities(), &[1]);
+        // the deleted entity keeps its buffers until its deletion can no longer be undone
+        assert_eq!(backend.live_buffer_count(), buffers);
+        assert_eq!(backend.live_texture_count(), 1);
+    }
+
+    #[test]
+    fn deletions_are_undone_and_redone_with_their_entities() {
+        let (backend, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(1.0, 0.0, 0.0);
+            options.add_quad(2.0, 0.0, 0.0);
+        });
+        let buffers = backend.live_buffer_count();
+
+        renderer.select(&[2, 0]);
+        renderer.key_down("Delete", false);
+        process_input(&mut renderer, 0.0);
+        assert_eq!(positions(&renderer), vec![1.0]);
+        assert_eq!(renderer.take_history_change(), Some((true, false)));
+
+        renderer.key_down(&key_name("z", true, false, false), false);
+        process_input(&mut renderer, 16.0);
+        assert_eq!(positions(&renderer), vec![0.0, 1.0, 2.0]);
+        assert_eq!(renderer.selection.entities(), &[0, 2]);
+        assert_eq!(renderer.take_history_change(), Some((false, true)));
+
+        renderer.key_down(&key_name("Z", true, false, true), false);
+        process_input(&mut renderer, 32.0);
+        assert_eq!(positions(&renderer), vec![1.0]);
+        assert_eq!(renderer.selection.entities(), &[0]);
+
+        // a new edit drops the redo, the entities it held can go
+        renderer.undo();
+        renderer.select(&[1]);
+        renderer.delete_selected();
+        assert!(!renderer.history.can_redo());
+        assert_eq!(backend.live_buffer_count(), buffers);
+
+        renderer.free();
+        assert_eq!(backend.live_buffer_count(), 0);
+    }
+
+    #[test]
+    fn additions_are_undone_and_redone() {
+        let (backend, mut renderer) = scene(|options| options.add_quad(0.0, 0.0, 0.0));
+        let buffers = backend.live_buffer_count();
+        let cube = EntityDescription {
+            kind: EntityKind::Cube,
+            position: Vector3::new(3.0, 0.0, 0.0),
+        };
+
+        assert_eq!(renderer.add_entity(&cube), Ok(1));
+        assert_eq!(renderer.entities[1].position, cube.position);
+        assert_eq!(renderer.selection.entities(), &[1]);
+        assert_eq!(renderer.take_history_change(), Some((true, false)));
+
+        renderer.undo();
+        assert_eq!(positions(&renderer), vec![0.0]);
+        assert!(renderer.selection.entities().is_empty());
+        renderer.redo();
+        assert_eq!(positions(&renderer), vec![0.0, 3.0]);
+        assert_eq!(renderer.selection.entities(), &[1]);
+
+        // an undone addition is released once it can no longer be redone
+        renderer.undo();
+        renderer.select(&[0]);
+        renderer.delete_selected();
+        assert!(!renderer.history.can_redo());
+        assert_eq!(backend.live_buffer_count(), buffers);
+    }
+
+    #[test]
+    fn text_changes_are_undone_and_redone() {
+        let (backend, mut renderer) =
+            scene(|options| options.add_text("ab".to_string(), 0.0, 0.0, 0.0));
+        let width = |renderer: &Renderer| renderer.entities[0].world_bounds(1.0).max.x;
+        let before = width(&renderer);
+
+        renderer.set_text(0, "abcd").unwrap();
+        let after = width(&renderer);
+        assert!(after > before);
+        assert_eq!(renderer.selection.entities(), &[0]);
+        assert_eq!(renderer.take_history_change(), Some((true, false)));
+
+        renderer.undo();
+        assert_eq!(width(&renderer), before);
+        renderer.redo();
+        assert_eq!(width(&renderer), after);
+
+        // out of the scene, nothing to change
+        renderer.set_text(1, "x").unwrap();
+        assert_eq!(renderer.take_history_change(), None);
+
+        // the history holds the mesh not drawn until it goes
+        renderer.free();
+        assert_eq!(backend.live_buffer_count(), 0);
+    }
+
+    #[test]
+    fn a_drag_is_undone_in_one_step() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.set_snap_grid(0.25).unwrap();
+        });
+        let camera = renderer.camera.clone();
+        let screen = |x, y| camera.world_to_screen(Vector3::new(x, y, 0.0));
+
+        for (index, x) in [1.0, 2.0].iter().enumerate() {
+            renderer.pointer_down(1, screen(x - 1.0, 0.0), DragMode::Pan, false);
+            for (step, dx) in [0.5, 0.25, 0.0].iter().enumerate() {
+                renderer.pointer_move(1, screen(x - dx, 0.0));
+                process_input(&mut renderer, (index * 100 + step * 16) as f64);
+            }
+            renderer.pointer_up(1);
+        }
+        assert_eq!(renderer.entities[0].position, Vector3::new(2.0, 0.0, 0.0));
+        let moves = renderer.take_finished_moves();
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[1].from(), vec![1.0, 0.0, 0.0]);
+
+        renderer.undo();
+        assert_eq!(renderer.entities[0].position, Vector3::new(1.0, 0.0, 0.0));
+        renderer.undo();
+        assert_eq!(renderer.entities[0].position, Vector3::new(0.0, 0.0, 0.0));
+        assert!(!renderer.history.can_undo());
+        // the host page hears about the entities moving back
+        let moves = renderer.take_finished_moves();
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[1].to(), vec![0.0, 0.0, 0.0]);
+
+        renderer.redo();
+        assert_eq!(renderer.entities[0].position, Vector3::new(1.0, 0.0, 0.0));
+    }
+}
@@ -1,68 +1,69 @@
 extern crate core;
 
+mod actions;
+pub mod backend;
+mod camera;
+mod camera_motion;
 mod drawable;
+mod entity_drag;
+mod error;
+mod handle;
+mod history;
 mod material;
 mod mesh;
+pub mod mesh_data;
+mod options;
+mod overlay;
+mod picking;
+mod pointers;
 mod primitive;
+mod selection;
+mod stats;
+mod timestep;
+pub mod vertex_layout;
+mod wheel;
 
+pub use crate::actions::Action;
+use crate::actions::{Bindings, KeyboardInput};
+use crate::backend::{Backend, TextureSource, WebGlBackend};
+pub use crate::camera::{Camera, Projection, Ray, WorldRect};
+use crate::camera_motion::CameraMotion;
 use crate::drawable::Drawable;
+pub use crate::entity_drag::EntityMove;
+use crate::entity_drag::{EntityDrag, SnapSettings};
+pub use crate::error::{ErrorHandler, RendererError, ShaderStage};
+pub use crate::handle::RendererHandle;
+use crate::handle::{FrameRequester, HostCallbacks};
+use crate::history::{Command, Edit, History};
 use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::mesh_data::MeshData;
+use crate::options::{EntityDescription, EntityKind};
+pub use crate::options::{RenderMode, RendererOptions, VertexFormat};
+use crate::overlay::{SelectionBoxOverlay, StatsOverlay};
+pub use crate::picking::Hit;
+use crate::picking::PickingPass;
+use crate::pointers::Pointers;
 use crate::primitive::Primitive;
-use crate::MouseState::{Down, Drag, Up};
-use crate::ZoomState::{Idle, In, Out};
-use gloo::render::{request_animation_frame, AnimationFrame};
-use nalgebra::{Matrix4, Orthographic3, Point3, Vector2, Vector3};
-use std::f32::consts::{PI, TAU};
+use crate::selection::{BoxSelect, Selection};
+pub use crate::stats::FrameStats;
+use crate::stats::FrameTimes;
+use crate::timestep::FixedTimestep;
+use crate::wheel::{WheelInput, WheelSettings};
+use crate::MouseState::{Down, Drag, Gesture, Up};
+use gloo::render::AnimationFrame;
+use nalgebra::{Vector2, Vector3};
+use std::f32::consts::TAU;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys::{
-    Document, HtmlCanvasElement, HtmlImageElement, MouseEvent, WebGlProgram, WebGlRenderingContext,
-    WebGlShader, WebGlTexture, WheelEvent, Window,
-};
-
-#[macro_export]
-macro_rules! debug_web_number {
-    ( $($div_name:expr, $value:expr)?) => {
-        $(
-        document()
-            .get_element_by_id($div_name)
-            .unwrap()
-            .set_text_content(Some(format!("{:<5.1}", $value).as_str()));
-        )?
-    };
-}
-
-#[macro_export]
-macro_rules! debug_web_vector2 {
-    ( $($div_name:expr, $value:expr)?) => {
-        $(
-        document()
-            .get_element_by_id($div_name)
-            .unwrap()
-            .set_text_content(Some(format!("{}, {}", $value.x, $value.y).as_str()));
-        )?
-    };
-}
-
-#[macro_export]
-macro_rules! debug_web_vector3 {
-    ( $($div_name:expr, $value:expr)?) => {
-        $(
-        document()
-            .get_element_by_id($div_name)
-            .unwrap()
-            .set_text_content(Some(format!("{}, {}, {}", $value.x, $value.y, $value.z).as_str()));
-        )?
-    };
-}
-
-static mut RENDERER: Option<Renderer> = None;
+use web_sys::{Document, HtmlCanvasElement, MouseEvent, Window};
 
 #[wasm_bindgen]
 extern "C" {
-    fn alert(s: &str);
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn log_error(s: &str);
 }
 
 #[derive(PartialEq)]
@@ -70,363 +71,908 @@ enum MouseState {
     Up,
     Down,
     Drag,
+    /// Two pointers or more are down, they pinch, turn and move the view.
+    Gesture,
 }
 
-#[derive(PartialEq)]
-enum ZoomState {
-    In,
-    Out,
-    Idle,
+/// What dragging with the mouse does.
+#[derive(Copy, Clone, PartialEq)]
+enum DragMode {
+    /// Keep the grabbed world position under the mouse.
+    Pan,
+    /// Turn around the target, only with the perspective projection.
+    Orbit,
+    /// Draw a rubber band selecting the entities it touches, the camera stays.
+    Select,
+    /// Move the selected entities, started on the grabbed one.
+    Move,
 }
 
 pub struct Renderer {
-    gl: WebGlRenderingContext,
-    animation_handler: AnimationFrame,
-    last_update: i32,
-    display_width: i32,
-    display_height: i32,
-    aspect: f32,
-    field_of_view: f32,
-    z_near: f32,
-    z_far: f32,
-    camera_pos: Vector3<f32>,
-    projection_matrix: Orthographic3<f32>,
-    model_view_matrix: Matrix4<f32>,
-    zoom: f32,
-    zoom_state: ZoomState,
+    backend: Box<dyn Backend>,
+    animation_handler: Option<AnimationFrame>,
+    timestep: FixedTimestep,
+    /// Something moved during the last simulation step.
+    animating: bool,
+    /// How far drawn frames are between the previous simulation step and the current one.
+    interpolation: f32,
+    camera: Camera,
+    /// Camera at the start of the current simulation step, drawn frames interpolate from it.
+    previous_camera: Camera,
+    /// View the renderer started with, `Action::ResetView` flies back to it.
+    home: Camera,
+    camera_motion: CameraMotion,
+    wheel_input: WheelInput,
+    wheel_settings: WheelSettings,
+    bindings: Bindings,
+    keyboard: KeyboardInput,
+    /// Program and texture shared by every entity.
+    material: Material,
+    vertex_format: VertexFormat,
     entities: Vec<Primitive>,
+    selection: Selection,
+    /// Selection as last given to the host page.
+    reported_selection: Selection,
+    /// A modifier was held when the pointer went down: clicks toggle entities in and out of
+    /// the selection and drags from empty space draw a rubber band.
+    toggle_selection: bool,
+    box_select: Option<BoxSelect>,
+    selection_box: Option<SelectionBoxOverlay>,
+    /// Entity under the pointer when it went down, dragging moves it.
+    grabbed: Option<usize>,
+    entity_drag: Option<EntityDrag>,
+    snap: SnapSettings,
+    /// Shift is held, dragged entities only move along x or y.
+    axis_lock: bool,
+    /// Moves of the drags that ended since the host page was last told.
+    finished_moves: Vec<EntityMove>,
+    /// Edits of `entities`, to undo and redo them.
+    history: History,
+    /// Picks by drawing ids off-screen when enabled, by testing triangles otherwise.
+    picking_pass: Option<PickingPass>,
+    /// Index in `entities` of the entity under the hovering mouse.
+    hovered: Option<usize>,
+    /// The last pointer went up without dragging, the click events that follow pick the scene.
+    clicked: bool,
     last_mouse_position: Vector2<f32>,
     current_mouse_position: Vector2<f32>,
     mouse_down_init_position: Vector2<f32>,
     mouse_drag_init_world_position: Vector3<f32>,
     mouse_state: MouseState,
-    sample_delta: Vec<f32>,
+    pointers: Pointers,
+    drag_mode: DragMode,
+    frame_times: FrameTimes,
+    /// Statistics as of the last drawn frame.
+    stats: FrameStats,
+    overlay: Option<StatsOverlay>,
+    render_mode: RenderMode,
+    /// Something changed since the last drawn frame, only used in [`RenderMode::OnDemand`].
+    needs_redraw: bool,
+    error_handler: ErrorHandler,
+    /// Last error given to the handler, so a failure repeating every frame is reported once.
+    last_error: Option<RendererError>,
+    /// Errors waiting for the renderer to be released, the handler may call back into it.
+    pending_errors: Vec<RendererError>,
 }
 
-#[wasm_bindgen]
-pub fn run() {
-    console_error_panic_hook::set_once();
+impl Renderer {
+    pub(crate) fn new(
+        backend: Box<dyn Backend>,
+        options: &RendererOptions,
+        error_handler: ErrorHandler,
+        display_width: i32,
+        display_height: i32,
+    ) -> Result<Renderer, RendererError> {
+        let shader_program =
+            backend.create_program(include_str!("vs.glsl"), include_str!("fs.glsl"))?;
+        let texture = backend.create_texture(TextureSource::Url(options.texture_url.clone()))?;
+        let material = Material::new(shader_program, texture);
 
-    let canvas = canvas();
+        backend.set_clear_color(options.background_color);
 
-    let closure = Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
-        let mouse_pos = get_mouse_position(event);
-        let mut renderer = unsafe { RENDERER.as_mut().unwrap() };
-        renderer.current_mouse_position = mouse_pos;
-    });
-    document()
-        .add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref())
-        .expect("failed to setup mousemove callback");
-    closure.forget();
+        let entities = options
+            .scene
+            .iter()
+            .map(|description| {
+                create_entity(
+                    backend.as_ref(),
+                    &material,
+                    options.vertex_format,
+                    description,
+                )
+            })
+            .collect::<Result<_, RendererError>>()?;
 
-    let closure = Closure::<dyn FnMut(_)>::new(move |event: MouseEvent| {
-        let mut renderer = unsafe { RENDERER.as_mut().unwrap() };
-        renderer.mouse_state = Down;
-        renderer.mouse_down_init_position = get_mouse_position(event);
-    });
-    document()
-        .add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())
-        .expect("failed to setup mousedown callback");
-    closure.forget();
-
-    let closure = Closure::<dyn FnMut(_)>::new(move |_event: MouseEvent| {
-        let mut renderer = unsafe { RENDERER.as_mut().unwrap() };
-        renderer.mouse_state = Up;
-    });
-    document()
-        .add_event_listener_with_callback("mouseup", closure.as_ref().unchecked_ref())
-        .expect("failed to setup mouseup callback");
-    closure.forget();
-
-    let closure = Closure::<dyn FnMut(_)>::new(move |event: WheelEvent| {
-        let mut renderer = unsafe { RENDERER.as_mut().unwrap() };
-        if event.delta_y() > 0.0 {
-            renderer.zoom_state = In;
+        let picking_pass = if options.gpu_picking {
+            Some(PickingPass::new(
+                backend.as_ref(),
+                options.background_color,
+            )?)
         } else {
-            renderer.zoom_state = Out;
-        }
-    });
-    document()
-        .add_event_listener_with_callback("wheel", closure.as_ref().unchecked_ref())
-        .expect("failed to setup wheel callback");
-    closure.forget();
-
-    let gl = canvas
-        .get_context("webgl")
-        .expect("unable to initialize WebGL, your browser or machine may not support it.")
-        .expect("failed to retrieve context")
-        .dyn_into::<WebGlRenderingContext>()
-        .expect("failed to convert context into webgl context");
-
-    let vertex_shader_source = include_str!("vs.glsl");
-    let fragment_shader_source = include_str!("fs.glsl");
-    let shader_program = init_shader_program(&gl, vertex_shader_source, fragment_shader_source)
-        .expect("failed to create shader program");
-    let texture = load_texture(&gl, "http://localhost:8000/texture/rust_logo.png");
-    let material = Material::new(shader_program, texture);
-
-    gl.clear_color(0.0, 0.0, 0.0, 1.0);
-    gl.clear_depth(1.0);
-    gl.enable(WebGlRenderingContext::DEPTH_TEST);
-    gl.depth_func(WebGlRenderingContext::LEQUAL);
-    gl.enable(WebGlRenderingContext::CULL_FACE);
-    gl.pixel_storei(WebGlRenderingContext::UNPACK_FLIP_Y_WEBGL, 1);
-
-    let mut entities: Vec<Primitive> = Vec::new();
-    // let factor = 3.464_101_6;
-    // for x in -6..7 {
-    //     for y in -0..1 {
-    //         let mut quad = Primitive::new_quad(&gl, material.clone());
-    //         quad.position = Vector3::new(x as f32 * factor, y as f32 * factor, 0.0);
-    //         entities.push(quad);
-    //     }
-    // }
-
-    entities.push(Primitive::new_text(&gl, material, "Salut la team"));
-
-    unsafe {
-        RENDERER = Some(Renderer {
-            gl,
-            animation_handler: request_animation_frame(update),
-            last_update: 0,
-            display_width: canvas.client_width(),
-            display_height: canvas.client_height(),
-            aspect: canvas.client_width() as f32 / canvas.client_height() as f32,
-            field_of_view: 45.0 * PI / 180.0,
-            z_near: 0.1,
-            z_far: 100.0,
-            camera_pos: Vector3::new(0.0, 0.0, 10.0),
-            projection_matrix: Orthographic3::from_fov(1.0, 1.0, 0.0, 1.0), // dummy projection
-            model_view_matrix: Default::default(),
-            zoom: 1.0,
-            zoom_state: ZoomState::Idle,
+            None
+        };
+
+        let mut camera = Camera::new(options.camera_position, display_width, display_height);
+        camera.set_zoom_limits(options.min_zoom, options.max_zoom);
+        camera.set_zoom(options.zoom);
+        camera.set_projection(options.projection);
+
+        Ok(Renderer {
+            backend,
+            animation_handler: None,
+            timestep: FixedTimestep::new(options.tick_rate),
+            animating: false,
+            interpolation: 1.0,
+            home: camera.clone(),
+            previous_camera: camera.clone(),
+            camera,
+            camera_motion: CameraMotion::new(options.pan_friction),
+            wheel_input: Default::default(),
+            bindings: options.bindings.clone(),
+            keyboard: Default::default(),
+            wheel_settings: WheelSettings {
+                zoom_sensitivity: options.zoom_sensitivity,
+                pan_sensitivity: options.pan_sensitivity,
+            },
+            material,
+            vertex_format: options.vertex_format,
             entities,
+            selection: Default::default(),
+            reported_selection: Default::default(),
+            toggle_selection: false,
+            box_select: None,
+            selection_box: None,
+            grabbed: None,
+            entity_drag: None,
+            snap: SnapSettings {
+                grid: options.snap_grid,
+                to_entities: options.snap_to_entities,
+            },
+            axis_lock: false,
+            finished_moves: Vec::new(),
+            history: Default::default(),
+            picking_pass,
+            hovered: None,
+            clicked: false,
             last_mouse_position: Default::default(),
             current_mouse_position: Default::default(),
             mouse_down_init_position: Default::default(),
             mouse_drag_init_world_position: Default::default(),
             mouse_state: MouseState::Up,
-            sample_delta: vec![],
+            pointers: Default::default(),
+            drag_mode: DragMode::Pan,
+            frame_times: Default::default(),
+            stats: Default::default(),
+            overlay: None,
+            render_mode: options.render_mode,
+            needs_redraw: true,
+            error_handler,
+            last_error: None,
+            pending_errors: Vec::new(),
         })
     }
-}
 
-fn update(timestamp: f64) {
-    let timestamp = timestamp as i32;
-    let mut renderer = unsafe { RENDERER.as_mut().unwrap() };
-    let delta_time = ((timestamp - renderer.last_update) as f32) * 0.001;
-    renderer.last_update = timestamp;
+    /// Draw the next frame even in [`RenderMode::OnDemand`].
+    fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+        // whatever changed may have moved the entities on screen
+        if let Some(picking_pass) = &mut self.picking_pass {
+            picking_pass.invalidate();
+        }
+    }
 
-    renderer.sample_delta.push(delta_time);
-    if renderer.sample_delta.len() >= 10 {
-        renderer.sample_delta.remove(0);
+    fn report(&mut self, error: RendererError) {
+        if self.last_error.as_ref() != Some(&error) {
+            self.last_error = Some(error.clone());
+            self.pending_errors.push(error);
+        }
     }
-    let avg_tps: f32 = renderer.sample_delta.iter().sum();
-    let avg_tps = avg_tps / renderer.sample_delta.len() as f32;
-    debug_web_number!["tps", avg_tps * 1000.0];
 
-    renderer.last_mouse_position = renderer.current_mouse_position;
-    update_mouse_state(renderer);
+    /// Errors reported since they were last taken, with the handler to give them to.
+    fn take_errors(&mut self) -> (Vec<RendererError>, ErrorHandler) {
+        (
+            std::mem::take(&mut self.pending_errors),
+            self.error_handler.clone(),
+        )
+    }
 
-    let world_pos = get_world_pos_from_viewport_pos(renderer.last_mouse_position);
+    /// Bring the scene back once the browser restored a lost context.
+    fn restore_context(&mut self) -> Result<(), RendererError> {
+        self.backend.restore()?;
+        // the loss may have caused errors that are worth reporting if they come back
+        self.last_error = None;
+        self.request_redraw();
+        Ok(())
+    }
 
-    debug_web_vector2!["mouse_world_position", world_pos];
-    debug_web_vector3!["camera_position", renderer.camera_pos];
+    /// `toggle` tells whether a selection modifier is held.
+    fn pointer_down(&mut self, id: i32, position: Vector2<f32>, drag_mode: DragMode, toggle: bool) {
+        self.pointers.press(id, position);
+        if self.pointers.len() == 1 {
+            self.mouse_state = Down;
+            self.mouse_down_init_position = position;
+            self.current_mouse_position = position;
+            self.toggle_selection = toggle;
+            self.grabbed = self.pick(position).map(|hit| hit.entity);
+            // with the modifier, dragging from empty space selects instead of moving the view
+            self.drag_mode = match self.grabbed {
+                Some(_) => DragMode::Move,
+                None if toggle => DragMode::Select,
+                None => drag_mode,
+            };
+        } else {
+            self.mouse_state = Gesture;
+            self.box_select = None;
+            // the fingers move the view now, the entities stay where they were dragged to
+            self.finish_entity_drag();
+        }
+    }
 
-    // move camera such that mouse pos is still in the same world space position
-    if Drag == renderer.mouse_state {
-        let mut offset = world_pos - renderer.mouse_drag_init_world_position;
-        offset.z = 0.0;
-        renderer.camera_pos -= offset;
+    /// Returns whether the pointer is down, moving it then changes the view.
+    fn pointer_move(&mut self, id: i32, position: Vector2<f32>) -> bool {
+        if !self.pointers.move_to(id, position) {
+            // a hovering mouse still anchors the wheel zoom
+            if self.pointers.len() == 0 {
+                self.current_mouse_position = position;
+            }
+            return false;
+        }
+        if self.pointers.primary().map(|pointer| pointer.id) == Some(id) {
+            self.current_mouse_position = position;
+        }
+        true
     }
 
-    if Idle != renderer.zoom_state {
-        match renderer.zoom_state {
-            In => {
-                renderer.zoom *= 1.1;
+    /// Returns whether the pointer was down.
+    fn pointer_up(&mut self, id: i32) -> bool {
+        let click = self.mouse_state == Down && self.pointers.len() == 1;
+        if !self.pointers.release(id) {
+            return false;
+        }
+        self.clicked = click;
+        self.box_select = None;
+        self.finish_entity_drag();
+        if click {
+            self.click_select(self.current_mouse_position);
+        }
+        match self.pointers.primary().map(|pointer| pointer.position) {
+            None => self.mouse_state = Up,
+            // the pointer left from a gesture drags on from where it is
+            Some(position) if self.pointers.len() == 1 => {
+                self.mouse_state = Drag;
+                self.drag_mode = DragMode::Pan;
+                self.current_mouse_position = position;
+                self.last_mouse_position = position;
+                self.mouse_drag_init_world_position = self.camera.screen_to_focus_plane(position);
             }
-            Out => {
-                renderer.zoom *= 0.909_090_94;
+            Some(_) => {}
+        }
+        true
+    }
+
+    /// Camera as drawn, between the previous simulation step and the current one.
+    fn drawn_camera(&self) -> Camera {
+        let mut camera = self.camera.clone();
+        camera.blend_views(&self.previous_camera, &self.camera, self.interpolation);
+        camera
+    }
+
+    /// Topmost entity drawn at a canvas position.
+    fn pick(&mut self, screen: Vector2<f32>) -> Option<Hit> {
+        let camera = self.drawn_camera();
+        let ray = camera.screen_ray(screen)?;
+        let alpha = self.interpolation;
+        let hit = |(entity, primitive): (usize, &Primitive)| {
+            let (distance, local) = primitive.intersect(&ray, alpha)?;
+            Some(Hit {
+                entity,
+                x: local.x,
+                y: local.y,
+                z: local.z,
+                distance,
+            })
+        };
+
+        let picking_pass = match &mut self.picking_pass {
+            Some(picking_pass) => picking_pass,
+            None => {
+                return self
+                    .entities
+                    .iter()
+                    .enumerate()
+                    .filter_map(hit)
+                    .min_by(|a, b| a.distance.total_cmp(&b.distance))
             }
-            Idle => {}
-        }
-        renderer.zoom = renderer.zoom.clamp(0.1, 3.7);
-        renderer.zoom_state = Idle;
-        renderer.projection_matrix = Orthographic3::from_fov(
-            renderer.aspect,
-            renderer.field_of_view * renderer.zoom,
-            renderer.z_near,
-            renderer.z_far,
+        };
+        let entity = picking_pass.entity_at(
+            self.backend.as_ref(),
+            &camera,
+            &self.entities,
+            alpha,
+            screen,
         );
-        let current_world_pos = get_world_pos_from_viewport_pos(renderer.last_mouse_position);
-        let mut offset = world_pos - current_world_pos;
-        offset.z = 0.0;
-        renderer.camera_pos += offset;
+        match entity {
+            // on the silhouette the ray can narrowly miss the triangles of the drawn entity,
+            // nothing is hit then
+            Ok(entity) => entity.and_then(|entity| hit((entity, &self.entities[entity]))),
+            Err(error) => {
+                self.report(error);
+                None
+            }
+        }
     }
 
-    for entity in &mut renderer.entities {
-        let x_pos = entity.position.x;
-        let x = entity.rotation.x;
-        let y = entity.rotation.y;
-        let z = entity.rotation.z;
-        entity.rotation = Vector3::new(
-            // 0.0,
-            0.0,
-            // (x + delta_time * x_pos * 0.1) % TAU,
-            // (y + delta_time * x_pos * 0.1) % TAU,
-            0.0,
-            (z + delta_time * x_pos * 0.1) % TAU,
-        );
+    /// The mouse hovers `screen`, or left the canvas. Returns the new hit when another entity,
+    /// or none, is under the mouse.
+    fn hover(&mut self, screen: Option<Vector2<f32>>) -> Option<Option<Hit>> {
+        let hit = screen.and_then(|screen| self.pick(screen));
+        let entity = hit.map(|hit| hit.entity);
+        if entity == self.hovered {
+            return None;
+        }
+        self.hovered = entity;
+        Some(hit)
+    }
+
+    /// Returns whether the key is bound to an action.
+    fn key_down(&mut self, key: &str, repeat: bool) -> bool {
+        self.keyboard.key_down(&self.bindings, key, repeat)
+    }
+
+    /// A click selects the entity under it alone, or toggles it with the modifier.
+    fn click_select(&mut self, screen: Vector2<f32>) {
+        let entity = self.pick(screen).map(|hit| hit.entity);
+        match entity {
+            Some(entity) if self.toggle_selection => self.selection.toggle(entity),
+            // a missed click with the modifier keeps the selection
+            None if self.toggle_selection => {}
+            _ => self.selection.replace(entity),
+        }
+    }
+
+    /// Select `entities` alone, indices out of the scene are skipped.
+    fn select(&mut self, entities: &[usize]) {
+        self.selection.clear();
+        for &entity in entities {
+            if entity < self.entities.len() {
+                self.selection.add(entity);
+            }
+        }
+    }
+
+    /// The selection, when it changed since it was last taken.
+    fn take_selection_change(&mut self) -> Option<Vec<usize>> {
+        if self.selection == self.reported_selection {
+            return None;
+        }
+        self.reported_selection = self.selection.clone();
+        Some(self.selection.entities().to_vec())
     }
 
-    let canvas = canvas(); // todo: only work on chrome ???
-    renderer.display_width = canvas.client_width();
-    renderer.display_height = canvas.client_height();
-    // set draw buffer size to display size
-    canvas.set_width(renderer.display_width as u32);
-    canvas.set_height(renderer.display_height as u32);
+    /// Move the selection by `step` entities, wrapping around the scene.
+    fn select_next(&mut self, step: isize) {
+        let count = self.entities.len() as isize;
+        if count == 0 {
+            return;
+        }
+        let next = match self.selection.primary() {
+            Some(index) => index as isize + step,
+            None if step > 0 => 0,
+            None => count - 1,
+        };
+        self.selection
+            .replace(Some(next.rem_euclid(count) as usize));
+        self.request_redraw();
+    }
+
+    fn delete_selected(&mut self) {
+        let mut indices = self.selection.entities().to_vec();
+        if indices.is_empty() {
+            return;
+        }
+        indices.sort_unstable();
+        // the indices of a running drag are no longer valid
+        self.finish_entity_drag();
+        let mut command = Command::Delete {
+            indices,
+            removed: Vec::new(),
+        };
+        let edit = command.apply(&mut self.entities);
+        self.history.record(self.backend.as_ref(), command, false);
+        self.show_edit(edit);
+    }
+
+    /// Put a new entity in the scene and select it, returns its index.
+    fn add_entity(&mut self, description: &EntityDescription) -> Result<usize, RendererError> {
+        let entity = create_entity(
+            self.backend.as_ref(),
+            &self.material,
+            self.vertex_format,
+            description,
+        )?;
+        self.finish_entity_drag();
+        let index = self.entities.len();
+        let mut command = Command::Add {
+            index,
+            removed: Some(entity),
+        };
+        let edit = command.apply(&mut self.entities);
+        self.history.record(self.backend.as_ref(), command, false);
+        self.show_edit(edit);
+        Ok(index)
+    }
+
+    /// Replace the mesh of `entity` with `text`, indices out of the scene are skipped.
+    fn set_text(&mut self, entity: usize, text: &str) -> Result<(), RendererError> {
+        if entity >= self.entities.len() {
+            return Ok(());
+        }
+        let data = MeshData::text(text);
+        let mesh = Mesh::upload(self.backend.as_ref(), &data, self.vertex_format)?;
+        self.finish_entity_drag();
+        let mut command = Command::Retext { entity, mesh };
+        let edit = command.apply(&mut self.entities);
+        self.history.record(self.backend.as_ref(), command, false);
+        self.show_edit(edit);
+        Ok(())
+    }
+
+    fn undo(&mut self) {
+        self.finish_entity_drag();
+        if let Some(edit) = self.history.undo(&mut self.entities) {
+            self.show_edit(edit);
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_entity_drag();
+        if let Some(edit) = self.history.redo(&mut self.entities) {
+            self.show_edit(edit);
+        }
+    }
+
+    /// Select what an edit of the scene changed, the indices of the entities may have changed.
+    fn show_edit(&mut self, edit: Edit) {
+        self.hovered = None;
+        self.grabbed = None;
+        self.select(&edit.selection);
+        self.finished_moves.extend(edit.moves);
+        self.request_redraw();
+    }
+
+    /// Whether undo and redo are possible, when that changed since it was last taken.
+    fn take_history_change(&mut self) -> Option<(bool, bool)> {
+        self.history.take_change()
+    }
+
+    /// Start moving the selection from the grabbed entity, which joins the selection first.
+    fn start_entity_drag(&mut self) {
+        let grabbed = match self.grabbed {
+            Some(grabbed) if grabbed < self.entities.len() => grabbed,
+            _ => return,
+        };
+        if !self.selection.contains(grabbed) {
+            if self.toggle_selection {
+                self.selection.add(grabbed);
+            } else {
+                self.selection.replace(Some(grabbed));
+            }
+        }
+        let alpha = self.interpolation;
+        let describe = |index: usize| {
+            let entity = &self.entities[index];
+            (index, entity.position, entity.world_bounds(alpha))
+        };
+        let dragged = std::iter::once(grabbed)
+            .chain(
+                self.selection
+                    .entities()
+                    .iter()
+                    .copied()
+                    .filter(|&entity| entity != grabbed),
+            )
+            .map(describe)
+            .collect();
+        let others = (0..self.entities.len())
+            .filter(|&entity| !self.selection.contains(entity))
+            .map(|entity| self.entities[entity].world_bounds(alpha))
+            .collect();
+        // each drag is undone on its own
+        self.history.seal();
+        let grab = self.entities[grabbed].position;
+        let grab = self
+            .camera
+            .screen_ray(self.mouse_down_init_position)
+            .and_then(|ray| ray.intersect_plane(grab, Vector3::z()))
+            .unwrap_or(grab);
+        self.entity_drag = Some(EntityDrag::new(dragged, others, grab));
+    }
+
+    /// Move the dragged entities after the pointer at a canvas position.
+    fn drag_entities(&mut self, screen: Vector2<f32>) {
+        let entity_drag = match &mut self.entity_drag {
+            Some(entity_drag) => entity_drag,
+            None => return,
+        };
+        let positions = entity_drag.drag_to(&self.camera, screen, self.axis_lock, self.snap);
+        let moves: Vec<_> = positions
+            .into_iter()
+            .map(|(entity, position)| {
+                EntityMove::new(entity, self.entities[entity].position, position)
+            })
+            .collect();
+        if moves
+            .iter()
+            .all(|entity_move| entity_move.from == entity_move.to)
+        {
+            return;
+        }
+        for entity_move in &moves {
+            self.entities[entity_move.entity].move_to(entity_move.to);
+        }
+        // the steps of a drag merge into one command
+        self.history
+            .record(self.backend.as_ref(), Command::Move(moves), true);
+        self.request_redraw();
+    }
+
+    fn finish_entity_drag(&mut self) {
+        if let Some(entity_drag) = self.entity_drag.take() {
+            self.finished_moves.extend(entity_drag.moves());
+            self.history.seal();
+        }
+    }
+
+    /// Moves of the drags that ended since they were last taken.
+    fn take_finished_moves(&mut self) -> Vec<EntityMove> {
+        std::mem::take(&mut self.finished_moves)
+    }
 
-    draw_scene(renderer);
+    /// Stretch the rubber band to a canvas position and select what it touches.
+    fn drag_box_select(&mut self, screen: Vector2<f32>) {
+        let box_select = match &mut self.box_select {
+            Some(box_select) => box_select,
+            None => return,
+        };
+        box_select.to = self.camera.screen_to_world(screen);
+        let mut selection = box_select.base.clone();
+        for (index, entity) in self.entities.iter().enumerate() {
+            if box_select.touches(&entity.world_bounds(self.interpolation)) {
+                selection.add(index);
+            }
+        }
+        self.selection = selection;
+    }
 
-    renderer.animation_handler = request_animation_frame(update);
+    /// Corners of the rubber band on the canvas, while one is dragged.
+    fn selection_box_corners(&self) -> Option<[Vector2<f32>; 4]> {
+        let rect = self.box_select.as_ref()?.rect();
+        let corner = |x, y| self.camera.world_to_screen(Vector3::new(x, y, 0.0));
+        Some([
+            corner(rect.min_x, rect.min_y),
+            corner(rect.max_x, rect.min_y),
+            corner(rect.max_x, rect.max_y),
+            corner(rect.min_x, rect.max_y),
+        ])
+    }
+
+    /// Release every GPU resource owned by the scene.
+    fn free(&mut self) {
+        self.selection.clear();
+        self.box_select = None;
+        self.grabbed = None;
+        self.entity_drag = None;
+        self.finished_moves.clear();
+        self.history.clear(self.backend.as_ref());
+        self.hovered = None;
+        if let Some(mut picking_pass) = self.picking_pass.take() {
+            picking_pass.delete(self.backend.as_ref());
+        }
+        for entity in self.entities.drain(..) {
+            entity.delete_mesh(self.backend.as_ref());
+        }
+        self.material.delete(self.backend.as_ref());
+    }
 }
 
-fn draw_scene(renderer: &mut Renderer) {
-    let gl = &renderer.gl;
-    gl.viewport(0, 0, canvas().width() as i32, canvas().height() as i32);
-    gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT);
-
-    renderer.aspect = renderer.display_width as f32 / renderer.display_height as f32;
-    renderer.projection_matrix = Orthographic3::from_fov(
-        renderer.aspect,
-        renderer.field_of_view * renderer.zoom,
-        renderer.z_near,
-        renderer.z_far,
-    );
-    renderer.model_view_matrix = (Matrix4::new_translation(&renderer.camera_pos))
-        .try_inverse()
-        .unwrap();
+/// Start a renderer, without options it draws on the `#canvas` element.
+#[wasm_bindgen]
+pub fn run(options: Option<RendererOptions>) -> Result<RendererHandle, JsValue> {
+    console_error_panic_hook::set_once();
 
-    for entity in renderer.entities.as_slice() {
-        entity.draw(&renderer);
+    let options = options.unwrap_or_default();
+    let canvas = options.canvas_element()?;
+
+    let error_handler: ErrorHandler = match options.on_error.clone() {
+        Some(callback) => Rc::new(move |error| {
+            // the host callback throwing must not take the renderer down with it
+            let _ = callback.call1(&JsValue::NULL, &error.into());
+        }),
+        None => Rc::new(|error| log_error(&error.to_string())),
+    };
+    let frame_requester = FrameRequester::default();
+    let backend = WebGlBackend::new(&canvas, options.prefer_webgl2, error_handler.clone(), {
+        let frame_requester = frame_requester.clone();
+        Rc::new(move || frame_requester.request_frame())
+    })?;
+    let mut renderer = Renderer::new(
+        Box::new(backend),
+        &options,
+        error_handler,
+        canvas.client_width(),
+        canvas.client_height(),
+    )?;
+    if options.stats_overlay {
+        renderer.overlay = StatsOverlay::new(&canvas);
     }
+    renderer.selection_box = SelectionBoxOverlay::new(&canvas);
+
+    let callbacks = HostCallbacks {
+        on_click: options.on_click.clone(),
+        on_double_click: options.on_double_click.clone(),
+        on_hover: options.on_hover.clone(),
+        on_selection_change: options.on_selection_change.clone(),
+        on_move_end: options.on_move_end.clone(),
+        on_history_change: options.on_history_change.clone(),
+    };
+    Ok(RendererHandle::new(
+        renderer,
+        canvas,
+        frame_requester,
+        callbacks,
+    ))
 }
 
-fn init_shader_program(
-    gl: &WebGlRenderingContext,
-    vss: &str,
-    fss: &str,
-) -> Result<WebGlProgram, ()> {
-    let vertex_shader = load_shader(gl, WebGlRenderingContext::VERTEX_SHADER, vss)
-        .expect("failed to load vertex shader");
-    let fragment_shader = load_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, fss)
-        .expect("failed to load fragment shader");
-
-    let shader_program = gl
-        .create_program()
-        .expect("failed to create shader program");
-    gl.attach_shader(&shader_program, &vertex_shader);
-    gl.attach_shader(&shader_program, &fragment_shader);
-    gl.link_program(&shader_program);
-
-    if !gl.get_program_parameter(&shader_program, WebGlRenderingContext::LINK_STATUS) {
-        let _info_log = gl
-            .get_program_info_log(&shader_program)
-            .expect("can't retrieve information log");
-        alert(format!("an error occurred linking the shaders: {_info_log}").as_str());
-        return Err(());
-    }
-
-    Ok(shader_program)
+/// Run one frame, returns whether the next animation frame is needed.
+///
+/// Input is applied once per frame, the simulation advances by fixed steps and the scene is
+/// drawn interpolated between the last two of them.
+fn update(renderer: &mut Renderer, canvas: &HtmlCanvasElement, timestamp: f64) -> bool {
+    // the loop is paused on `webglcontextlost`, but frames can run before the event arrives
+    if renderer.backend.is_context_lost() {
+        return false;
+    }
+
+    renderer.frame_times.record(timestamp);
+
+    simulate(renderer, timestamp);
+
+    renderer
+        .camera
+        .set_viewport(canvas.client_width(), canvas.client_height());
+    // set draw buffer size to display size, only when it changed since it clears the canvas
+    let (width, height) = (canvas.client_width() as u32, canvas.client_height() as u32);
+    if (canvas.width(), canvas.height()) != (width, height) {
+        canvas.set_width(width);
+        canvas.set_height(height);
+        renderer.request_redraw();
+    }
+
+    present(renderer);
+    if let Some(overlay) = &mut renderer.overlay {
+        overlay.show(canvas, &renderer.stats, timestamp);
+    }
+    let corners = renderer.selection_box_corners();
+    if let Some(selection_box) = &mut renderer.selection_box {
+        selection_box.show(canvas, corners);
+    }
+
+    let keep_running = renderer.render_mode == RenderMode::Continuous
+        || renderer.animating
+        || renderer.camera_motion.is_active()
+        || renderer.keyboard.is_panning();
+    if !keep_running {
+        renderer.timestep.reset();
+        renderer.frame_times.pause();
+    }
+    keep_running
 }
 
-fn load_shader(gl: &WebGlRenderingContext, type_: u32, source: &str) -> Result<WebGlShader, ()> {
-    let shader = gl.create_shader(type_).expect("failed to create shader");
-    gl.shader_source(&shader, source);
-    gl.compile_shader(&shader);
-    if !gl.get_shader_parameter(&shader, WebGlRenderingContext::COMPILE_STATUS) {
-        let _info_log = gl
-            .get_shader_info_log(&shader)
-            .expect("can't retrieve information log");
-        alert(format!("an error occurred compiling the shaders: {_info_log}").as_str());
-        gl.delete_shader(Some(&shader));
-        return Err(());
-    }
-    Ok(shader)
+/// Apply the input of the frame drawn at `timestamp` and run the simulation steps it is due.
+fn simulate(renderer: &mut Renderer, timestamp: f64) {
+    process_input(renderer, timestamp);
+
+    for _ in 0..renderer.timestep.advance(timestamp) {
+        let step = renderer.timestep.step();
+        renderer.animating = tick(renderer, step);
+    }
+    renderer.interpolation = renderer.timestep.alpha();
+    if renderer.animating {
+        renderer.request_redraw();
+    }
 }
 
-fn load_texture(gl: &WebGlRenderingContext, path: &str) -> WebGlTexture {
-    let texture = gl.create_texture().expect("failed to create texture");
-    gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
-
-    let level = 0;
-    let internal_format = WebGlRenderingContext::RGBA;
-    let width = 1;
-    let height = 1;
-    let border = 0;
-    let source_format = WebGlRenderingContext::RGBA;
-    let source_type = WebGlRenderingContext::UNSIGNED_BYTE;
-    let pixel = [255, 0, 255, 255];
-    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-        WebGlRenderingContext::TEXTURE_2D,
-        level,
-        internal_format as i32,
-        width,
-        height,
-        border,
-        source_format,
-        source_type,
-        Some(&pixel),
-    )
-    .expect("failed to copy dummy texture data");
-
-    let image = HtmlImageElement::new().expect("failed to create new image");
-
-    let callback = Closure::<dyn Fn()>::new({
-        let gl = gl.clone();
-        let texture = texture.clone();
-        let image = image.clone();
-        move || {
-            gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
-            gl.tex_image_2d_with_u32_and_u32_and_image(
-                WebGlRenderingContext::TEXTURE_2D,
-                0,
-                WebGlRenderingContext::RGBA as i32,
-                WebGlRenderingContext::RGBA,
-                WebGlRenderingContext::UNSIGNED_BYTE,
-                &image,
-            )
-            .expect("failed to load image texture");
+/// Radians the camera orbits by for each pixel dragged.
+const ORBIT_SPEED: f32 = 0.005;
 
-            if is_power_of_2(image.width()) && is_power_of_2(image.height()) {
-                gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
-            } else {
-                gl.tex_parameteri(
-                    WebGlRenderingContext::TEXTURE_2D,
-                    WebGlRenderingContext::TEXTURE_MIN_FILTER,
-                    WebGlRenderingContext::LINEAR as i32,
-                );
-                gl.tex_parameteri(
-                    WebGlRenderingContext::TEXTURE_2D,
-                    WebGlRenderingContext::TEXTURE_WRAP_S,
-                    WebGlRenderingContext::CLAMP_TO_EDGE as i32,
-                );
-                gl.tex_parameteri(
-                    WebGlRenderingContext::TEXTURE_2D,
-                    WebGlRenderingContext::TEXTURE_WRAP_T,
-                    WebGlRenderingContext::CLAMP_TO_EDGE as i32,
-                );
+/// Apply the pending mouse and wheel input to the camera, once per frame so that it follows
+/// the pointer without latency. The motions it starts run in the simulation steps.
+fn process_input(renderer: &mut Renderer, timestamp: f64) {
+    let was_dragging = Drag == renderer.mouse_state;
+    let mouse_delta = renderer.current_mouse_position - renderer.last_mouse_position;
+    renderer.last_mouse_position = renderer.current_mouse_position;
+    update_mouse_state(renderer);
+
+    let camera = renderer.camera.clone();
+
+    if Drag == renderer.mouse_state && renderer.drag_mode == DragMode::Select {
+        if !was_dragging {
+            let from = renderer
+                .camera
+                .screen_to_world(renderer.mouse_down_init_position);
+            renderer.box_select = Some(BoxSelect {
+                from,
+                to: from,
+                base: renderer.selection.clone(),
+            });
+        }
+        renderer.drag_box_select(renderer.last_mouse_position);
+    } else if Drag == renderer.mouse_state && renderer.drag_mode == DragMode::Move {
+        if !was_dragging {
+            renderer.start_entity_drag();
+        }
+        renderer.drag_entities(renderer.last_mouse_position);
+    } else if Drag == renderer.mouse_state {
+        if !was_dragging {
+            renderer.camera_motion.stop();
+        }
+        if renderer.drag_mode == DragMode::Orbit
+            && renderer.camera.projection() == Projection::Perspective
+        {
+            if was_dragging {
+                renderer
+                    .camera
+                    .orbit(-mouse_delta.x * ORBIT_SPEED, mouse_delta.y * ORBIT_SPEED);
+            }
+        } else {
+            // move camera such that mouse pos is still in the same world space position
+            let world_pos = renderer
+                .camera
+                .screen_to_focus_plane(renderer.last_mouse_position);
+            let offset = renderer.mouse_drag_init_world_position - world_pos;
+            renderer.camera.translate(offset);
+            renderer.camera_motion.track_pan(offset, timestamp);
+        }
+    } else if was_dragging {
+        renderer.camera_motion.release_pan(timestamp);
+    }
+
+    // keep the world positions under both fingers under them
+    if Gesture == renderer.mouse_state {
+        if let Some(gesture) = renderer.pointers.gesture() {
+            renderer.camera_motion.stop();
+            let view = &mut renderer.camera;
+            view.magnify_around(gesture.scale, gesture.from);
+            view.rotate_around(gesture.rotation, gesture.from);
+            let offset =
+                view.screen_to_focus_plane(gesture.from) - view.screen_to_focus_plane(gesture.to);
+            view.translate(offset);
+        }
+    }
+    renderer.pointers.end_frame();
+
+    let wheel = std::mem::take(&mut renderer.wheel_input);
+    let anchor = renderer.last_mouse_position;
+    if wheel.zoom != 0.0 {
+        renderer
+            .camera_motion
+            .zoom_by(&renderer.camera, wheel.zoom.exp(), anchor);
+    }
+    // pinches and trackpad scrolls follow the fingers without easing
+    if wheel.pinch != 0.0 || wheel.pan != Vector2::zeros() {
+        renderer.camera_motion.stop();
+        renderer.camera.zoom_around(wheel.pinch.exp(), anchor);
+        renderer.camera.scroll(wheel.pan);
+    }
+
+    process_keyboard(renderer);
+
+    // the camera follows the input right away, drawn frames do not interpolate to it
+    if camera != renderer.camera {
+        renderer.previous_camera = renderer.camera.clone();
+        renderer.request_redraw();
+    }
+}
+
+/// Pixels per second the view scrolls by while a pan key is held.
+const KEY_PAN_SPEED: f32 = 600.0;
+/// Zoom factor of one zoom key press.
+const KEY_ZOOM_STEP: f32 = 1.25;
+/// Seconds it takes to fly back to the start view.
+const RESET_VIEW_DURATION: f32 = 0.4;
+
+/// Held pan keys move the camera in the simulation steps.
+fn process_keyboard(renderer: &mut Renderer) {
+    let center = Vector2::new(
+        renderer.camera.viewport_width() as f32,
+        renderer.camera.viewport_height() as f32,
+    ) / 2.0;
+    for action in renderer.keyboard.take_pressed() {
+        match action {
+            Action::ZoomIn => {
+                renderer
+                    .camera_motion
+                    .zoom_by(&renderer.camera, 1.0 / KEY_ZOOM_STEP, center)
+            }
+            Action::ZoomOut => {
+                renderer
+                    .camera_motion
+                    .zoom_by(&renderer.camera, KEY_ZOOM_STEP, center)
             }
+            Action::ResetView => renderer.camera_motion.fly_to_view(
+                &renderer.camera,
+                renderer.home.clone(),
+                RESET_VIEW_DURATION,
+            ),
+            Action::SelectNext => renderer.select_next(1),
+            Action::SelectPrevious => renderer.select_next(-1),
+            Action::Delete => renderer.delete_selected(),
+            Action::Undo => renderer.undo(),
+            Action::Redo => renderer.redo(),
+            Action::PanLeft | Action::PanRight | Action::PanUp | Action::PanDown => {}
         }
-    });
+    }
+}
+
+/// Advance the simulation by one step of `step` seconds, returns whether anything moved.
+fn tick(renderer: &mut Renderer, step: f32) -> bool {
+    renderer.previous_camera = renderer.camera.clone();
+    let held = |action| renderer.keyboard.is_held(action) as i32 as f32;
+    let direction = Vector2::new(
+        held(Action::PanRight) - held(Action::PanLeft),
+        held(Action::PanDown) - held(Action::PanUp),
+    );
+    if direction != Vector2::zeros() {
+        renderer.camera_motion.stop();
+        renderer.camera.scroll(direction * KEY_PAN_SPEED * step);
+    }
+    let dragging = Drag == renderer.mouse_state;
+    renderer
+        .camera_motion
+        .update(&mut renderer.camera, step, dragging);
+    let mut animating = renderer.camera != renderer.previous_camera;
+
+    for entity in &mut renderer.entities {
+        entity.snapshot();
+        let x_pos = entity.position.x;
+        let z = entity.rotation.z;
+        let rotation = Vector3::new(0.0, 0.0, (z + step * x_pos * 0.1) % TAU);
+        animating |= rotation != entity.rotation;
+        entity.rotation = rotation;
+    }
+    animating
+}
 
-    image.set_onload(Some(callback.as_ref().unchecked_ref()));
-    image.set_cross_origin(Some("anonymous"));
-    image.set_src(path);
+/// Draw the scene if it changed since the last frame, returns whether a frame was drawn.
+fn present(renderer: &mut Renderer) -> bool {
+    if renderer.render_mode == RenderMode::OnDemand && !renderer.needs_redraw {
+        return false;
+    }
+    renderer.needs_redraw = false;
+    renderer.backend.begin_frame();
+    let result = draw_scene(renderer);
+    renderer.backend.end_frame();
+    renderer.stats = FrameStats::new(
+        &renderer.frame_times,
+        renderer.backend.take_frame_counters(),
+    );
+    if let Err(error) = result {
+        renderer.report(error);
+    }
+    true
+}
 
-    callback.forget();
+fn draw_scene(renderer: &mut Renderer) -> Result<(), RendererError> {
+    renderer.backend.viewport(
+        renderer.camera.viewport_width(),
+        renderer.camera.viewport_height(),
+    );
+    renderer.backend.clear();
 
-    texture
+    for entity in renderer.entities.as_slice() {
+        entity.draw(renderer)?;
+    }
+    Ok(())
 }
 
 fn update_mouse_state(renderer: &mut Renderer) {
@@ -436,16 +982,16 @@ fn update_mouse_state(renderer: &mut Renderer) {
             let mouse_delta = renderer.current_mouse_position - renderer.mouse_down_init_position;
             if mouse_delta.magnitude() > 2.0 {
                 renderer.mouse_state = Drag;
-                renderer.mouse_drag_init_world_position =
-                    get_world_pos_from_viewport_pos(renderer.current_mouse_position);
+                renderer.mouse_drag_init_world_position = renderer
+                    .camera
+                    .screen_to_focus_plane(renderer.current_mouse_position);
             }
         }
-        Drag => {}
+        Drag | Gesture => {}
     }
 }
 
-fn get_mouse_position(event: MouseEvent) -> Vector2<f32> {
-    let canvas = canvas();
+fn get_mouse_position(canvas: &HtmlCanvasElement, event: &MouseEvent) -> Vector2<f32> {
     let rect = canvas.get_bounding_client_rect();
     let scale_x = canvas.width() as f32 / rect.width() as f32;
     let scale_y = canvas.height() as f32 / rect.height() as f32;
@@ -455,20 +1001,22 @@ fn get_mouse_position(event: MouseEvent) -> Vector2<f32> {
     )
 }
 
-fn get_world_pos_from_viewport_pos(pos: Vector2<f32>) -> Vector3<f32> {
-    let renderer = unsafe { RENDERER.as_ref().unwrap() };
-    let y_view = (pos.y - renderer.display_height as f32).abs();
-    let x_clip = pos.x / renderer.display_width as f32 * 2.0 - 1.0;
-    let y_clip = y_view / renderer.display_height as f32 * 2.0 - 1.0;
-    let clip_space_pos = Point3::new(x_clip, y_clip, 1.0);
-    let transformation_camera = Matrix4::new_translation(&renderer.camera_pos);
-    let view_space_pos = renderer.projection_matrix.unproject_point(&clip_space_pos);
-    let world_pos = Matrix4::new_translation(&view_space_pos.coords) * transformation_camera;
-    Vector3::new(world_pos.m14, world_pos.m24, world_pos.m34)
-}
-
-fn is_power_of_2(value: u32) -> bool {
-    value & (value - 1) == 0
+fn create_entity(
+    backend: &dyn Backend,
+    material: &Material,
+    vertex_format: VertexFormat,
+    description: &EntityDescription,
+) -> Result<Primitive, RendererError> {
+    let data = match &description.kind {
+        EntityKind::Quad => MeshData::quad(),
+        EntityKind::Cube => MeshData::cube(),
+        EntityKind::Text(text) => MeshData::text(text),
+    };
+    let mesh = Mesh::upload(backend, &data, vertex_format)?;
+    let mut entity = Primitive::new(mesh, material.clone());
+    entity.position = description.position;
+    entity.snapshot();
+    Ok(entity)
 }
 
 fn window() -> Window {
@@ -481,11 +1029,228 @@ fn document() -> Document {
         .expect("should have a document on window")
 }
 
-fn canvas() -> HtmlCanvasElement {
-    document()
-        .get_element_by_id("canvas")
-        .expect("failed to get #canvas element")
-        .dyn_into::<HtmlCanvasElement>()
-        .map_err(|_| ())
-        .expect("failed to get html canvas element")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Call, RecordingBackend, UniformValue};
+    use nalgebra::Matrix4;
+
+    /// Renderer of an 800x600 canvas over `backend`, any reported error fails the test.
+    pub(crate) fn renderer(backend: &RecordingBackend, options: &RendererOptions) -> Renderer {
+        let error_handler: ErrorHandler = Rc::new(|error| panic!("unexpected error: {}", error));
+        Renderer::new(Box::new(backend.clone()), options, error_handler, 800, 600).unwrap()
+    }
+
+    /// Renderer over a new recording backend, with the default options changed by `configure`.
+    pub(crate) fn setup(
+        configure: impl FnOnce(&mut RendererOptions),
+    ) -> (RecordingBackend, Renderer) {
+        let mut options = RendererOptions::default();
+        configure(&mut options);
+        let backend = RecordingBackend::new();
+        let renderer = renderer(&backend, &options);
+        (backend, renderer)
+    }
+
+    /// Like [`setup`], with only the entities `build` adds in the scene.
+    pub(crate) fn scene(build: impl FnOnce(&mut RendererOptions)) -> (RecordingBackend, Renderer) {
+        setup(|options| {
+            options.clear_scene();
+            build(options);
+        })
+    }
+
+    /// X coordinate of every entity, in scene order.
+    pub(crate) fn positions(renderer: &Renderer) -> Vec<f32> {
+        renderer
+            .entities
+            .iter()
+            .map(|entity| entity.position.x)
+            .collect()
+    }
+
+    #[test]
+    fn flattened_entities_are_drawn_and_not_picked() {
+        let (_, mut renderer) = scene(|options| options.add_quad(0.0, 0.0, 0.0));
+        renderer.entities[0].scale = Vector3::zeros();
+
+        draw_scene(&mut renderer).unwrap();
+
+        assert!(renderer.pick(Vector2::new(400.0, 300.0)).is_none());
+    }
+
+    #[test]
+    fn draws_entities_in_scene_order() {
+        let (backend, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(1.0, 0.0, 0.0);
+        });
+        backend.clear_calls();
+
+        draw_scene(&mut renderer).unwrap();
+
+        let calls = backend.calls();
+        assert_eq!(&calls[..2], &[Call::Viewport(800, 600), Call::Clear]);
+        let draws: Vec<_> = calls
+            .iter()
+            .filter(|call| matches!(call, Call::DrawTriangles(_)))
+            .collect();
+        assert_eq!(
+            draws,
+            vec![&Call::DrawTriangles(6), &Call::DrawTriangles(36)]
+        );
+    }
+
+    #[test]
+    fn transformation_uniform_follows_entity_position() {
+        let (backend, mut renderer) = scene(|options| options.add_quad(2.0, -1.0, 0.0));
+
+        draw_scene(&mut renderer).unwrap();
+
+        let transformation = backend.calls().into_iter().find_map(|call| match call {
+            Call::SetUniform(_, name, UniformValue::Mat4(matrix))
+                if name == "uTransformationMatrix" =>
+            {
+                Some(matrix)
+            }
+            _ => None,
+        });
+        assert_eq!(
+            transformation,
+            Some(Matrix4::new_translation(&Vector3::new(2.0, -1.0, 0.0)))
+        );
+    }
+
+    #[test]
+    fn scene_is_drawn_with_the_same_resources_after_a_restore() {
+        let (backend, mut renderer) = setup(|_| {});
+        draw_scene(&mut renderer).unwrap();
+        let before = backend.calls();
+
+        backend.lose_context();
+        assert!(draw_scene(&mut renderer).is_err());
+        renderer.restore_context().unwrap();
+        backend.clear_calls();
+        draw_scene(&mut renderer).unwrap();
+
+        let binds = |calls: &[Call]| -> Vec<Call> {
+            calls
+                .iter()
+                .filter(|call| matches!(call, Call::BindVertexArray(_) | Call::BindTexture(..)))
+                .cloned()
+                .collect()
+        };
+        assert_eq!(binds(&backend.calls()), binds(&before));
+        assert_eq!(backend.live_buffer_count(), 2);
+    }
+
+    #[test]
+    fn on_demand_mode_only_draws_after_a_change() {
+        let (_, mut renderer) = setup(|_| {});
+
+        assert!(present(&mut renderer));
+        assert!(!present(&mut renderer));
+        renderer.request_redraw();
+        assert!(present(&mut renderer));
+        assert!(!present(&mut renderer));
+    }
+
+    #[test]
+    fn continuous_mode_draws_every_frame() {
+        let (backend, mut renderer) =
+            setup(|options| options.set_render_mode(RenderMode::Continuous));
+        backend.clear_calls();
+
+        assert!(present(&mut renderer));
+        assert!(present(&mut renderer));
+        let clears = backend
+            .calls()
+            .iter()
+            .filter(|call| **call == Call::Clear)
+            .count();
+        assert_eq!(clears, 2);
+    }
+
+    #[test]
+    fn dragging_keeps_the_grabbed_position_under_the_mouse() {
+        let (_, mut renderer) = setup(|_| {});
+        let grabbed = renderer.camera.screen_to_world(Vector2::new(103.0, 100.0));
+        renderer.mouse_state = Down;
+        renderer.mouse_down_init_position = Vector2::new(100.0, 100.0);
+        renderer.current_mouse_position = Vector2::new(103.0, 100.0);
+        process_input(&mut renderer, 0.0);
+        renderer.needs_redraw = false;
+
+        renderer.current_mouse_position = Vector2::new(300.0, 250.0);
+        process_input(&mut renderer, 16.0);
+
+        let under_mouse = renderer.camera.screen_to_world(Vector2::new(300.0, 250.0));
+        assert!((under_mouse - grabbed).abs().max() < 1e-4);
+        assert!(renderer.needs_redraw);
+    }
+
+    #[test]
+    fn dragging_orbits_a_perspective_camera() {
+        let (_, mut renderer) = setup(|options| options.set_projection(Projection::Perspective));
+        let target = renderer.camera.target();
+        renderer.mouse_state = Down;
+        renderer.drag_mode = DragMode::Orbit;
+        renderer.mouse_down_init_position = Vector2::new(100.0, 100.0);
+        renderer.current_mouse_position = Vector2::new(103.0, 100.0);
+        process_input(&mut renderer, 0.0);
+
+        renderer.current_mouse_position = Vector2::new(200.0, 150.0);
+        process_input(&mut renderer, 16.0);
+
+        assert_eq!(renderer.camera.target(), target);
+        let eye = renderer.camera.position() - target;
+        assert!(eye.x < 0.0 && eye.y > 0.0);
+    }
+
+    #[test]
+    fn drawn_camera_interpolates_between_steps() {
+        let (_, mut renderer) = setup(|_| {});
+        renderer.key_down("ArrowRight", false);
+        simulate(&mut renderer, 0.0);
+        simulate(&mut renderer, 1000.0 / 60.0);
+        let (from, to) = (renderer.previous_camera.target(), renderer.camera.target());
+        assert!(to.x > from.x);
+
+        renderer.interpolation = 0.25;
+        let drawn = renderer.drawn_camera().target();
+        assert!((drawn - from.lerp(&to, 0.25)).abs().max() < 1e-6);
+    }
+
+    #[test]
+    fn free_releases_scene_resources() {
+        let deletions = |backend: &RecordingBackend| {
+            let calls = backend.calls();
+            let programs = calls
+                .iter()
+                .filter(|call| matches!(call, Call::DeleteProgram(_)))
+                .count();
+            let textures = calls
+                .iter()
+                .filter(|call| matches!(call, Call::DeleteTexture(_)))
+                .count();
+            (programs, textures)
+        };
+
+        let (backend, mut renderer) = setup(|options| options.add_quad(2.0, 0.0, 0.0));
+        assert_eq!(backend.live_buffer_count(), 4);
+
+        renderer.free();
+
+        assert_eq!(backend.live_buffer_count(), 0);
+        assert_eq!(backend.live_texture_count(), 0);
+        // the entities share the material, it goes once
+        assert_eq!(deletions(&backend), (1, 1));
+
+        let (backend, mut empty) = scene(|_| {});
+
+        empty.free();
+
+        assert_eq!(backend.live_texture_count(), 0);
+        assert_eq!(deletions(&backend), (1, 1));
+    }
 }
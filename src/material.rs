@@ -1,46 +1,38 @@
-use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlTexture};
+use crate::backend::{Backend, ProgramId, TextureId, UniformValue};
+use crate::error::RendererError;
 use crate::Renderer;
 
 #[derive(Clone)]
 pub struct Material {
-    pub shader: WebGlProgram,
-    pub texture: WebGlTexture,
+    pub shader: ProgramId,
+    pub texture: TextureId,
 }
 
 impl Material {
-    pub fn bind(&self, renderer: &Renderer) {
-        let gl = &renderer.gl;
-        gl.use_program(Some(&self.shader));
-        gl.active_texture(WebGlRenderingContext::TEXTURE - 1);
-        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.texture));
-        let u_sampler_location = gl
-            .get_uniform_location(&self.shader, "uSampler")
-            .expect("can't get uSampler location");
-        gl.uniform1i(Some(&u_sampler_location), 0);
-
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(
-                &gl.get_uniform_location(&self.shader, "uProjectionMatrix")
-                    .expect("can't get projection matrix location"),
-            ),
-            false,
-            renderer.projection_matrix.as_matrix().as_slice(),
-        );
+    pub fn bind(&self, renderer: &Renderer) -> Result<(), RendererError> {
+        let backend = renderer.backend.as_ref();
+        let camera = renderer.drawn_camera();
+        backend.use_program(self.shader);
+        backend.bind_texture(0, self.texture);
+        backend.set_uniform(self.shader, "uSampler", UniformValue::Int(0))?;
+        backend.set_uniform(
+            self.shader,
+            "uProjectionMatrix",
+            UniformValue::Mat4(camera.projection_matrix()),
+        )?;
+        backend.set_uniform(
+            self.shader,
+            "uModelViewMatrix",
+            UniformValue::Mat4(camera.view_matrix()),
+        )
+    }
 
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(
-                &gl.get_uniform_location(&self.shader, "uModelViewMatrix")
-                    .expect("can't get model view matrix location"),
-            ),
-            false,
-            renderer.model_view_matrix.as_slice(),
-        );
+    pub fn delete(&self, backend: &dyn Backend) {
+        backend.delete_program(self.shader);
+        backend.delete_texture(self.texture);
     }
 
-    pub fn new(shader: WebGlProgram, texture: WebGlTexture) -> Material {
-        Material {
-            shader,
-            texture,
-        }
+    pub fn new(shader: ProgramId, texture: TextureId) -> Material {
+        Material { shader, texture }
     }
-}
\ No newline at end of file
+}
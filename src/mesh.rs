@@ -1,433 +1,409 @@
-use web_sys::{WebGlBuffer, WebGlRenderingContext};
-use crate::material::Material;
-
-struct VertexBufferObject {
-    position: WebGlBuffer,
-    uv: WebGlBuffer,
-    normal: WebGlBuffer,
-    indices: WebGlBuffer,
+use crate::backend::{Backend, BufferId, ProgramId, VertexArrayId};
+use crate::camera::Ray;
+use crate::error::RendererError;
+use crate::mesh_data::MeshData;
+use crate::options::VertexFormat;
+use crate::picking::{intersect_triangle, Bounds};
+use crate::vertex_layout::{AttributeStream, VertexLayout};
+use nalgebra::Vector3;
+use std::cell::RefCell;
+
+/// Vertices 16-bit indices can address. The last index is left out, WebGL2 always restarts
+/// primitives on it.
+pub(crate) const U16_VERTICES: usize = 0xffff;
+
+/// Vertex buffer with the layout of the vertices it holds.
+struct VertexStream {
+    buffer: BufferId,
+    layout: VertexLayout,
 }
 
-pub struct Mesh {
-    vbo: VertexBufferObject,
-    pub vertex_count: i32,
+/// Vertices and the triangles between them, drawn in one draw call.
+struct MeshPart {
+    streams: Vec<VertexStream>,
+    index_buffer: BufferId,
+    index_count: i32,
+    /// Vertex arrays already recorded for this part, one per program it was drawn with.
+    vertex_arrays: RefCell<Vec<(ProgramId, VertexArrayId)>>,
 }
 
-impl Mesh {
-    pub fn bind(&self, gl: &WebGlRenderingContext, material: &Material) {
-        let shader = &material.shader;
-        // Position
-        // get the location of the aVertexPosition shader param
-        let attrib_vertex_position = gl.get_attrib_location(shader, "aVertexPosition") as u32;
-        gl.bind_buffer(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            Some(&self.vbo.position),
-        );
-        gl.vertex_attrib_pointer_with_i32(
-            attrib_vertex_position,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            0,
-            0,
-        );
-        gl.enable_vertex_attrib_array(attrib_vertex_position);
-
-        // Uv
-        let attrib_uv = gl.get_attrib_location(shader, "aTextureCoord") as u32;
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.vbo.uv));
-        gl.vertex_attrib_pointer_with_i32(attrib_uv, 2, WebGlRenderingContext::FLOAT, false, 0, 0);
-        gl.enable_vertex_attrib_array(attrib_uv);
-
-        // Normal
-        let attrib_normal = gl.get_attrib_location(shader, "aVertexNormal") as u32;
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.vbo.normal));
-        gl.vertex_attrib_pointer_with_i32(
-            attrib_normal,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            0,
-            0,
-        );
-        gl.enable_vertex_attrib_array(attrib_normal);
+impl MeshPart {
+    fn upload(
+        backend: &dyn Backend,
+        data: &MeshData,
+        format: VertexFormat,
+    ) -> Result<MeshPart, RendererError> {
+        let streams = match format {
+            VertexFormat::Interleaved => {
+                let layout = data.attributes.iter().map(AttributeStream::attribute).fold(
+                    VertexLayout::new(),
+                    |layout, attribute| {
+                        layout.attribute(
+                            &attribute.name,
+                            attribute.components,
+                            attribute.kind,
+                            attribute.normalized,
+                        )
+                    },
+                );
+                let values: Vec<_> = data
+                    .attributes
+                    .iter()
+                    .map(|stream| stream.data.as_slice())
+                    .collect();
+                vec![(layout.interleave(&values), layout)]
+            }
+            VertexFormat::Separate => data
+                .attributes
+                .iter()
+                .map(|stream| {
+                    let vertices = stream.layout.interleave(&[&stream.data]);
+                    (vertices, stream.layout.clone())
+                })
+                .collect(),
+        };
+        let streams = streams
+            .into_iter()
+            .map(|(vertices, layout)| {
+                Ok(VertexStream {
+                    buffer: backend.create_vertex_buffer_bytes(&vertices)?,
+                    layout,
+                })
+            })
+            .collect::<Result<_, RendererError>>()?;
+        // the smaller indices whenever they are enough, they are what WebGL1 always has
+        let index_buffer = if data.vertex_count() <= U16_VERTICES {
+            let indices: Vec<_> = data.indices.iter().map(|&index| index as u16).collect();
+            backend.create_index_buffer(&indices)?
+        } else {
+            backend.create_index_buffer_u32(&data.indices)?
+        };
+        Ok(MeshPart {
+            streams,
+            index_buffer,
+            index_count: data.indices.len() as i32,
+            vertex_arrays: Default::default(),
+        })
+    }
 
-        gl.bind_buffer(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-            Some(&self.vbo.indices),
-        );
+    /// Bind for any program, the attributes it does not read are left out.
+    fn draw(&self, backend: &dyn Backend, program: ProgramId) -> Result<(), RendererError> {
+        let vertex_array = self.vertex_array(backend, program)?;
+        backend.bind_vertex_array(vertex_array)?;
+        backend.draw_triangles(self.index_count);
+        Ok(())
     }
 
-    pub fn quad(gl: &WebGlRenderingContext) -> Mesh {
-        let position_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&position_buffer));
-        #[rustfmt::skip]
-            let vertices = [
-            // Front face
-            -1.0, -1.0, 0.0,
-            1.0, -1.0, 0.0,
-            1.0, 1.0, 0.0,
-            -1.0, 1.0, 0.0,
-        ];
-        unsafe {
-            let vertices = js_sys::Float32Array::view(&vertices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(vertices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+    fn vertex_array(
+        &self,
+        backend: &dyn Backend,
+        shader: ProgramId,
+    ) -> Result<VertexArrayId, RendererError> {
+        let mut vertex_arrays = self.vertex_arrays.borrow_mut();
+        if let Some((_, vertex_array)) =
+            vertex_arrays.iter().find(|(program, _)| *program == shader)
+        {
+            return Ok(*vertex_array);
         }
 
-        let uv_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&uv_buffer));
-        #[rustfmt::skip]
-            let uvs = [
-            // Front
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-        ];
-        unsafe {
-            let uvs = js_sys::Float32Array::view(&uvs);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(uvs),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+        let attributes: Vec<_> = self
+            .streams
+            .iter()
+            .flat_map(|stream| stream.layout.vertex_attributes(stream.buffer))
+            .collect();
+        let vertex_array = backend.create_vertex_array(shader, &attributes, self.index_buffer)?;
+        vertex_arrays.push((shader, vertex_array));
+        Ok(vertex_array)
+    }
 
-        let normal_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&normal_buffer));
-        #[rustfmt::skip]
-            let normals = [
-            // Front
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-        ];
-        unsafe {
-            let normals = js_sys::Float32Array::view(&normals);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(normals),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+    fn delete(&self, backend: &dyn Backend) {
+        for (_, vertex_array) in self.vertex_arrays.borrow_mut().drain(..) {
+            backend.delete_vertex_array(vertex_array);
         }
-
-        let index_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-            Some(&index_buffer),
-        );
-        #[rustfmt::skip]
-            let indices = [
-            0, 1, 2, 0, 2, 3,    // front
-        ];
-        unsafe {
-            let indices = js_sys::Uint16Array::view(&indices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-                &(indices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+        for stream in &self.streams {
+            backend.delete_buffer(stream.buffer);
         }
+        backend.delete_buffer(self.index_buffer);
+    }
+}
 
-        let vbo = VertexBufferObject {
-            position: position_buffer,
-            uv: uv_buffer,
-            normal: normal_buffer,
-            indices: index_buffer,
-        };
+pub struct Mesh {
+    /// A single part unless the mesh has more vertices than 16-bit indices address and the
+    /// backend has no 32-bit ones.
+    parts: Vec<MeshPart>,
+    /// Copies of the vertex positions and indices, to pick the mesh without reading back the
+    /// GPU buffers.
+    positions: Vec<Vector3<f32>>,
+    indices: Vec<u32>,
+    bounds: Bounds,
+}
 
-        Mesh {
-            vbo,
-            vertex_count: 6,
-        }
+impl Mesh {
+    /// Upload every attribute of `data`, laid out in `format`.
+    pub fn upload(
+        backend: &dyn Backend,
+        data: &MeshData,
+        format: VertexFormat,
+    ) -> Result<Mesh, RendererError> {
+        let parts =
+            if data.vertex_count() <= U16_VERTICES || backend.capabilities().element_index_uint {
+                vec![MeshPart::upload(backend, data, format)?]
+            } else {
+                data.split(U16_VERTICES)
+                    .iter()
+                    .map(|part| MeshPart::upload(backend, part, format))
+                    .collect::<Result<_, _>>()?
+            };
+        Ok(Mesh {
+            parts,
+            bounds: data.bounds(),
+            positions: data.positions(),
+            indices: data.indices.clone(),
+        })
     }
 
-    pub fn cube(gl: &WebGlRenderingContext) -> Mesh {
-        let position_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&position_buffer));
-        #[rustfmt::skip]
-            let vertices = [
-            // Front face
-            -1.0, -1.0, 1.0,
-            1.0, -1.0, 1.0,
-            1.0, 1.0, 1.0,
-            -1.0, 1.0, 1.0,
-
-            // Back face
-            -1.0, -1.0, -1.0,
-            -1.0, 1.0, -1.0,
-            1.0, 1.0, -1.0,
-            1.0, -1.0, -1.0,
-
-            // Top face
-            -1.0, 1.0, -1.0,
-            -1.0, 1.0, 1.0,
-            1.0, 1.0, 1.0,
-            1.0, 1.0, -1.0,
-
-            // Bottom face
-            -1.0, -1.0, -1.0,
-            1.0, -1.0, -1.0,
-            1.0, -1.0, 1.0,
-            -1.0, -1.0, 1.0,
-
-            // Right face
-            1.0, -1.0, -1.0,
-            1.0, 1.0, -1.0,
-            1.0, 1.0, 1.0,
-            1.0, -1.0, 1.0,
-
-            // Left face
-            -1.0, -1.0, -1.0,
-            -1.0, -1.0, 1.0,
-            -1.0, 1.0, 1.0,
-            -1.0, 1.0, -1.0,
-        ];
-        unsafe {
-            let vertices = js_sys::Float32Array::view(&vertices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(vertices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
 
-        let uv_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&uv_buffer));
-        #[rustfmt::skip]
-            let uvs = [
-            // Front
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-            // Back
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-            // Top
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-            // Bottom
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-            // Right
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-            // Left
-            0.0, 0.0,
-            1.0, 0.0,
-            1.0, 1.0,
-            0.0, 1.0,
-        ];
-        unsafe {
-            let uvs = js_sys::Float32Array::view(&uvs);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(uvs),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+    /// Distance along `ray`, given in the mesh coordinates, to the closest triangle it hits.
+    pub fn intersect(&self, ray: &Ray) -> Option<f32> {
+        if !self.bounds.is_hit(ray) {
+            return None;
         }
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let corner = |i: usize| self.positions[triangle[i] as usize];
+                intersect_triangle(ray, [corner(0), corner(1), corner(2)])
+            })
+            .min_by(|a, b| a.total_cmp(b))
+    }
 
-        let normal_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&normal_buffer));
-        #[rustfmt::skip]
-            let normals = [
-            // Front
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-            0.0, 0.0, 1.0,
-
-            // Back
-            0.0, 0.0, -1.0,
-            0.0, 0.0, -1.0,
-            0.0, 0.0, -1.0,
-            0.0, 0.0, -1.0,
-
-            // Top
-            0.0, 1.0, 0.0,
-            0.0, 1.0, 0.0,
-            0.0, 1.0, 0.0,
-            0.0, 1.0, 0.0,
-
-            // Bottom
-            0.0, -1.0, 0.0,
-            0.0, -1.0, 0.0,
-            0.0, -1.0, 0.0,
-            0.0, -1.0, 0.0,
-
-            // Right
-            1.0, 0.0, 0.0,
-            1.0, 0.0, 0.0,
-            1.0, 0.0, 0.0,
-            1.0, 0.0, 0.0,
-
-            // Left
-            -1.0, 0.0, 0.0,
-            -1.0, 0.0, 0.0,
-            -1.0, 0.0, 0.0,
-            -1.0, 0.0, 0.0
-        ];
-        unsafe {
-            let normals = js_sys::Float32Array::view(&normals);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(normals),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+    /// Draw with `program`, whose uniforms are already set, one draw call per part.
+    pub fn draw(&self, backend: &dyn Backend, program: ProgramId) -> Result<(), RendererError> {
+        for part in &self.parts {
+            part.draw(backend, program)?;
         }
+        Ok(())
+    }
 
-        let index_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-            Some(&index_buffer),
-        );
-        #[rustfmt::skip]
-            let indices = [
-            0, 1, 2, 0, 2, 3,    // front
-            4, 5, 6, 4, 6, 7,    // back
-            8, 9, 10, 8, 10, 11,   // top
-            12, 13, 14, 12, 14, 15,   // bottom
-            16, 17, 18, 16, 18, 19,   // right
-            20, 21, 22, 20, 22, 23,   // left
-        ];
-        unsafe {
-            let indices = js_sys::Uint16Array::view(&indices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-                &(indices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
+    pub fn delete(&self, backend: &dyn Backend) {
+        for part in &self.parts {
+            part.delete(backend);
         }
+    }
+}
 
-        let vbo = VertexBufferObject {
-            position: position_buffer,
-            uv: uv_buffer,
-            normal: normal_buffer,
-            indices: index_buffer,
-        };
-
-        Mesh {
-            vbo,
-            vertex_count: 36,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::recording::BufferData;
+    use crate::backend::AttributeType;
+    use crate::backend::{Call, Capabilities, RecordingBackend, VertexAttribute};
+    use crate::mesh_data::MeshBuilder;
+    use nalgebra::Vector2;
+
+    fn indices(backend: &RecordingBackend, mesh: &Mesh) -> Vec<u16> {
+        match backend.buffer_data(mesh.parts[0].index_buffer) {
+            Some(BufferData::Index(indices)) => indices,
+            other => panic!("expected an index buffer, got {:?}", other),
         }
     }
 
-    pub fn text(gl: &WebGlRenderingContext, input: &str) -> Mesh {
-        let mut vertices: Vec<f32> = Vec::new();
-        let mut uvs: Vec<f32> = Vec::new();
-        let mut normals: Vec<f32> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
-
-        let mut vertex_count = 0;
-        let mut index_count: u16 = 0;
-        for (i, char) in input.chars().enumerate() {
-            if char == ' ' {
-                continue;
-            }
-            let if32 = i as f32 * 2.0;
-            #[rustfmt::skip]
-                let vertices_t = [
-                -1.0 + if32, -1.0, 0.0,
-                1.0 + if32, -1.0, 0.0,
-                1.0 + if32, 1.0, 0.0,
-                -1.0 + if32, 1.0, 0.0];
-            vertices.extend_from_slice(&vertices_t);
-            #[rustfmt::skip]
-                let uvs_t = [
-                0.0, 0.0,
-                1.0, 0.0,
-                1.0, 1.0,
-                0.0, 1.0,
-            ];
-            uvs.extend_from_slice(&uvs_t);
-            #[rustfmt::skip]
-                let normals_t = [
-                0.0, 0.0, 1.0,
-                0.0, 0.0, 1.0,
-                0.0, 0.0, 1.0,
-                0.0, 0.0, 1.0
-            ];
-            normals.extend_from_slice(&normals_t);
-            let indices_t = [
-                index_count, 1 + index_count, 2 + index_count,
-                index_count, 2 + index_count, 3 + index_count
-            ];
-            indices.extend_from_slice(&indices_t);
-            index_count += 4;
-            vertex_count += 6;
+    fn buffer_bytes(backend: &RecordingBackend, buffer: BufferId) -> Vec<u8> {
+        match backend.buffer_data(buffer) {
+            Some(BufferData::VertexBytes(bytes)) => bytes,
+            other => panic!("expected a vertex buffer, got {:?}", other),
         }
+    }
 
-        let position_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&position_buffer));
-        unsafe {
-            let vertices = js_sys::Float32Array::view(&vertices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(vertices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+    fn vertices(backend: &RecordingBackend, buffer: BufferId) -> Vec<f32> {
+        buffer_bytes(backend, buffer)
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
 
-        let uv_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&uv_buffer));
-        unsafe {
-            let uvs = js_sys::Float32Array::view(&uvs);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(uvs),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+    fn vertex_attributes(backend: &RecordingBackend) -> Vec<VertexAttribute> {
+        backend
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                Call::CreateVertexArray(_, _, attributes, _) => Some(attributes.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
 
-        let normal_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&normal_buffer));
-        unsafe {
-            let normals = js_sys::Float32Array::view(&normals);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ARRAY_BUFFER,
-                &(normals),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+    #[test]
+    fn cube_has_six_faces() {
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &MeshData::cube(), VertexFormat::Separate).unwrap();
+        let streams = &mesh.parts[0].streams;
+        assert_eq!(mesh.parts[0].index_count, 36);
+        assert_eq!(vertices(&backend, streams[0].buffer).len(), 24 * 3);
+        assert_eq!(vertices(&backend, streams[1].buffer).len(), 24 * 2);
+        assert_eq!(vertices(&backend, streams[2].buffer).len(), 24 * 3);
+        assert!(indices(&backend, &mesh).iter().all(|&index| index < 24));
+    }
 
-        let index_buffer = gl.create_buffer().expect("failed to create buffer");
-        gl.bind_buffer(
-            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-            Some(&index_buffer),
+    #[test]
+    fn text_skips_spaces_but_keeps_their_advance() {
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &MeshData::text("a b"), VertexFormat::Separate).unwrap();
+        assert_eq!(mesh.parts[0].index_count, 12);
+        assert_eq!(
+            indices(&backend, &mesh),
+            vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]
         );
-        unsafe {
-            let indices = js_sys::Uint16Array::view(&indices);
-            gl.buffer_data_with_array_buffer_view(
-                WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
-                &(indices),
-                WebGlRenderingContext::STATIC_DRAW,
-            );
-        }
+        let positions = vertices(&backend, mesh.parts[0].streams[0].buffer);
+        // first corner of the second glyph sits two characters to the right
+        assert_eq!(&positions[12..15], &[3.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn interleaved_vertices_share_one_buffer() {
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &MeshData::quad(), VertexFormat::Interleaved).unwrap();
+        assert_eq!(mesh.parts[0].streams.len(), 1);
+        let buffer = mesh.parts[0].streams[0].buffer;
+        let vertices = vertices(&backend, buffer);
+        assert_eq!(vertices.len(), 4 * 8);
+        // position, texture coordinates and normal of the first corner
+        assert_eq!(&vertices[..8], &[-1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        let layout: Vec<_> = vertex_attributes(&backend)
+            .into_iter()
+            .map(|attribute| (attribute.buffer, attribute.stride, attribute.offset))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![(buffer, 32, 0), (buffer, 32, 12), (buffer, 32, 20)]
+        );
+    }
 
-        let vbo = VertexBufferObject {
-            position: position_buffer,
-            uv: uv_buffer,
-            normal: normal_buffer,
-            indices: index_buffer,
+    #[test]
+    fn large_meshes_use_32_bit_indices_or_are_split() {
+        // 16400 glyphs of 4 vertices, more than 16-bit indices address
+        let data = MeshData::text(&"a".repeat(16400));
+        let draws = |backend: &RecordingBackend| -> Vec<Call> {
+            backend
+                .calls()
+                .into_iter()
+                .filter(|call| matches!(call, Call::DrawTriangles(_)))
+                .collect()
         };
 
-        Mesh {
-            vbo,
-            vertex_count,
-        }
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &data, VertexFormat::Interleaved).unwrap();
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        assert!(matches!(
+            backend.buffer_data(mesh.parts[0].index_buffer),
+            Some(BufferData::IndexU32(indices)) if indices[16399 * 6] == 16399 * 4
+        ));
+        assert_eq!(draws(&backend), vec![Call::DrawTriangles(16400 * 6)]);
+
+        let webgl1 = RecordingBackend::with_capabilities(Capabilities {
+            element_index_uint: false,
+            ..RecordingBackend::new().capabilities()
+        });
+        let mesh = Mesh::upload(&webgl1, &data, VertexFormat::Interleaved).unwrap();
+        mesh.draw(&webgl1, ProgramId(100)).unwrap();
+        // parts are cut between triangles, the last glyph of the first part is cut in two
+        assert_eq!(
+            draws(&webgl1),
+            vec![
+                Call::DrawTriangles(16383 * 6 + 3),
+                Call::DrawTriangles(17 * 6 - 3)
+            ]
+        );
+        assert!(matches!(
+            webgl1.buffer_data(mesh.parts[1].index_buffer),
+            Some(BufferData::Index(_))
+        ));
+    }
+
+    #[test]
+    fn vertex_array_is_recorded_once_per_program() {
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &MeshData::quad(), VertexFormat::Interleaved).unwrap();
+
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        mesh.draw(&backend, ProgramId(101)).unwrap();
+
+        let created = backend
+            .calls()
+            .iter()
+            .filter(|call| matches!(call, Call::CreateVertexArray(..)))
+            .count();
+        assert_eq!(created, 2);
+        assert_eq!(backend.live_vertex_array_count(), 2);
+    }
+
+    #[test]
+    fn attributes_unused_by_the_program_are_skipped() {
+        let backend = RecordingBackend::new();
+        backend.remove_attribute("aVertexNormal");
+        let mesh = Mesh::upload(&backend, &MeshData::quad(), VertexFormat::Interleaved).unwrap();
+
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+
+        let names: Vec<_> = vertex_attributes(&backend)
+            .into_iter()
+            .map(|attribute| attribute.name)
+            .collect();
+        assert_eq!(names, vec!["aVertexPosition", "aTextureCoord"]);
+    }
+
+    #[test]
+    fn delete_releases_every_buffer() {
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &MeshData::quad(), VertexFormat::Interleaved).unwrap();
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        // one vertex buffer and the index buffer
+        assert_eq!(backend.live_buffer_count(), 2);
+        mesh.delete(&backend);
+        assert_eq!(backend.live_buffer_count(), 0);
+        assert_eq!(backend.live_vertex_array_count(), 0);
+    }
+
+    #[test]
+    fn vertex_colors_are_uploaded_with_the_other_attributes() {
+        let mut builder =
+            MeshBuilder::new().attribute("aVertexColor", 4, AttributeType::UnsignedByte, true);
+        let corner = |builder: &mut MeshBuilder, x, y| {
+            builder.push_vertex(Vector3::new(x, y, 0.0), Vector2::zeros(), Vector3::z())
+        };
+        let a = corner(&mut builder, 0.0, 0.0);
+        let b = corner(&mut builder, 1.0, 0.0);
+        let c = corner(&mut builder, 0.0, 1.0);
+        builder.push_triangle(a, b, c);
+        builder.set_attribute(b, "aVertexColor", &[255, 0, 0, 255]);
+        let data = builder.build();
+
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &data, VertexFormat::Interleaved).unwrap();
+        mesh.draw(&backend, ProgramId(100)).unwrap();
+        let vertices = buffer_bytes(&backend, mesh.parts[0].streams[0].buffer);
+        // eight floats then the color on each vertex
+        assert_eq!(vertices.len(), 3 * 36);
+        assert_eq!(&vertices[36 + 32..36 + 36], &[255, 0, 0, 255]);
+        assert_eq!(&vertices[32..36], &[0, 0, 0, 0]);
+        let color = vertex_attributes(&backend)
+            .into_iter()
+            .find(|attribute| attribute.name == "aVertexColor")
+            .unwrap();
+        assert_eq!((color.stride, color.offset), (36, 32));
+        assert_eq!(color.kind, AttributeType::UnsignedByte);
+        assert!(color.normalized);
+
+        let backend = RecordingBackend::new();
+        let mesh = Mesh::upload(&backend, &data, VertexFormat::Separate).unwrap();
+        let colors = buffer_bytes(&backend, mesh.parts[0].streams[3].buffer);
+        assert_eq!(colors, vec![0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0]);
     }
 }
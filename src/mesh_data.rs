@@ -0,0 +1,434 @@
+use crate::backend::AttributeType;
+use crate::picking::Bounds;
+use crate::vertex_layout::{AttributeStream, NORMAL, POSITION, TEXTURE_COORD};
+use nalgebra::{Matrix3, Matrix4, Point3, Rotation3, Vector2, Vector3};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Vertices and triangles of a mesh on the CPU, before they are uploaded as a
+/// [`crate::mesh::Mesh`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct MeshData {
+    /// One stream per attribute, each with a value for every vertex.
+    pub attributes: Vec<AttributeStream>,
+    /// Three vertex indices per triangle, counter-clockwise seen from the front.
+    pub indices: Vec<u32>,
+}
+
+/// No vertices yet, with the attributes of the default shader.
+impl Default for MeshData {
+    fn default() -> MeshData {
+        MeshData {
+            attributes: vec![
+                AttributeStream::new(POSITION, 3, AttributeType::Float, false),
+                AttributeStream::new(TEXTURE_COORD, 2, AttributeType::Float, false),
+                AttributeStream::new(NORMAL, 3, AttributeType::Float, false),
+            ],
+            indices: Vec::new(),
+        }
+    }
+}
+
+impl MeshData {
+    /// Square of side 2 around the origin, facing +z.
+    pub fn quad() -> MeshData {
+        let mut builder = MeshBuilder::new();
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let vertices = corners.map(|(x, y)| {
+            let uv = Vector2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0);
+            builder.push_vertex(Vector3::new(x, y, 0.0), uv, Vector3::zeros())
+        });
+        builder.push_quad(vertices);
+        builder.compute_normals();
+        builder.build()
+    }
+
+    /// Cube of side 2 around the origin, each face textured on its own.
+    pub fn cube() -> MeshData {
+        let quad = MeshData::quad();
+        // rotations turning the front face into the front, back, top, bottom, right and left
+        let faces = [
+            (Vector3::y(), 0.0),
+            (Vector3::y(), PI),
+            (Vector3::x(), -FRAC_PI_2),
+            (Vector3::x(), FRAC_PI_2),
+            (Vector3::y(), FRAC_PI_2),
+            (Vector3::y(), -FRAC_PI_2),
+        ];
+        let mut builder = MeshBuilder::new();
+        for (axis, angle) in faces {
+            let rotation = Rotation3::new(axis * angle).to_homogeneous();
+            let face = rotation * Matrix4::new_translation(&Vector3::z());
+            builder.append(&quad, &face);
+        }
+        builder.build()
+    }
+
+    /// One quad per character, two units apart. Spaces leave a gap.
+    pub fn text(input: &str) -> MeshData {
+        let quad = MeshData::quad();
+        let mut builder = MeshBuilder::new();
+        for (i, char) in input.chars().enumerate() {
+            if char == ' ' {
+                continue;
+            }
+            let advance = Vector3::new(i as f32 * 2.0, 0.0, 0.0);
+            builder.append(&quad, &Matrix4::new_translation(&advance));
+        }
+        builder.build()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeStream> {
+        self.attributes.iter().find(|stream| stream.name() == name)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.attributes
+            .first()
+            .map_or(0, AttributeStream::vertex_count)
+    }
+
+    pub fn positions(&self) -> Vec<Vector3<f32>> {
+        self.attribute(POSITION).map_or_else(Vec::new, |stream| {
+            (0..stream.vertex_count())
+                .map(|vertex| stream.vector3(vertex))
+                .collect()
+        })
+    }
+
+    pub(crate) fn bounds(&self) -> Bounds {
+        Bounds::around(&self.positions())
+    }
+
+    /// Cut the mesh into meshes of at most `max_vertices` vertices, without cutting any
+    /// triangle. Vertices shared by triangles of different parts are copied into each.
+    pub fn split(&self, max_vertices: usize) -> Vec<MeshData> {
+        let mut parts = Vec::new();
+        let mut part = self.empty_like();
+        // index of each vertex of `self` in the part being built
+        let mut copies: HashMap<u32, u32> = HashMap::new();
+        for triangle in self.indices.chunks_exact(3) {
+            let new_vertices = triangle
+                .iter()
+                .filter(|index| !copies.contains_key(index))
+                .count();
+            if part.vertex_count() + new_vertices > max_vertices {
+                parts.push(std::mem::replace(&mut part, self.empty_like()));
+                copies.clear();
+            }
+            let mut corners = [0; 3];
+            for (corner, &index) in corners.iter_mut().zip(triangle) {
+                *corner = *copies
+                    .entry(index)
+                    .or_insert_with(|| part.copy_vertex(self, index as usize));
+            }
+            part.indices.extend_from_slice(&corners);
+        }
+        if !part.indices.is_empty() {
+            parts.push(part);
+        }
+        parts
+    }
+
+    /// No vertices, with the attributes of `self`.
+    fn empty_like(&self) -> MeshData {
+        MeshData {
+            attributes: self
+                .attributes
+                .iter()
+                .map(|stream| AttributeStream {
+                    layout: stream.layout.clone(),
+                    data: Vec::new(),
+                })
+                .collect(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Add `vertex` of `source`, which has the same attributes, and return its new index.
+    fn copy_vertex(&mut self, source: &MeshData, vertex: usize) -> u32 {
+        let index = self.vertex_count() as u32;
+        for (stream, values) in self.attributes.iter_mut().zip(&source.attributes) {
+            stream.data.extend_from_slice(values.value(vertex));
+        }
+        index
+    }
+}
+
+/// Builds a [`MeshData`] vertex by vertex and triangle by triangle.
+#[derive(Default)]
+pub struct MeshBuilder {
+    data: MeshData,
+}
+
+impl MeshBuilder {
+    pub fn new() -> MeshBuilder {
+        Default::default()
+    }
+
+    /// Give every vertex one more attribute, zero until set with
+    /// [`MeshBuilder::set_attribute`]. It replaces any attribute of the same name.
+    pub fn attribute(
+        mut self,
+        name: &str,
+        components: i32,
+        kind: AttributeType,
+        normalized: bool,
+    ) -> MeshBuilder {
+        let mut stream = AttributeStream::new(name, components, kind, normalized);
+        stream.push_zeros(self.data.vertex_count());
+        self.data.attributes.retain(|other| other.name() != name);
+        self.data.attributes.push(stream);
+        self
+    }
+
+    /// Returns the index of the new vertex, for the triangles using it. Its other attributes
+    /// are zero.
+    pub fn push_vertex(
+        &mut self,
+        position: Vector3<f32>,
+        uv: Vector2<f32>,
+        normal: Vector3<f32>,
+    ) -> u32 {
+        let index = self.next_index();
+        for stream in &mut self.data.attributes {
+            stream.push_zeros(1);
+        }
+        self.set_floats(index, POSITION, position.as_slice());
+        self.set_floats(index, TEXTURE_COORD, uv.as_slice());
+        self.set_floats(index, NORMAL, normal.as_slice());
+        index
+    }
+
+    /// Set the value of `vertex` in the `name` attribute to the little-endian components in
+    /// `value`.
+    ///
+    /// Panics if there is no such attribute or `value` is not the size of its values.
+    pub fn set_attribute(&mut self, vertex: u32, name: &str, value: &[u8]) {
+        let stream = self
+            .data
+            .attributes
+            .iter_mut()
+            .find(|stream| stream.name() == name)
+            .unwrap_or_else(|| panic!("the mesh has no {} attribute", name));
+        let size = stream.attribute().size();
+        let start = vertex as usize * size;
+        stream.data[start..start + size].copy_from_slice(value);
+    }
+
+    /// Set the value of `vertex` in the float attribute `name`.
+    pub fn set_floats(&mut self, vertex: u32, name: &str, values: &[f32]) {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        self.set_attribute(vertex, name, &bytes);
+    }
+
+    pub fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.data.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /// Two triangles between four corners, counter-clockwise seen from the front.
+    pub fn push_quad(&mut self, [a, b, c, d]: [u32; 4]) {
+        self.push_triangle(a, b, c);
+        self.push_triangle(a, c, d);
+    }
+
+    /// Add the vertices and triangles of `mesh`, moved by `transformation`. Its normals turn
+    /// with it. Attributes only one of the meshes has, or with different layouts, are zero
+    /// for the vertices of the other.
+    pub fn append(&mut self, mesh: &MeshData, transformation: &Matrix4<f32>) {
+        let offset = self.next_index();
+        let vertex_count = self.data.vertex_count();
+        for stream in &mesh.attributes {
+            if self.data.attribute(stream.name()).is_none() {
+                let mut missing = AttributeStream {
+                    layout: stream.layout.clone(),
+                    data: Vec::new(),
+                };
+                missing.push_zeros(vertex_count);
+                self.data.attributes.push(missing);
+            }
+        }
+        for stream in &mut self.data.attributes {
+            match mesh.attribute(stream.name()) {
+                Some(values) if values.layout == stream.layout => {
+                    stream.data.extend_from_slice(&values.data)
+                }
+                _ => stream.push_zeros(mesh.vertex_count()),
+            }
+        }
+
+        let normal_matrix = normal_matrix(transformation);
+        for vertex in offset..self.next_index() {
+            let position = self.vector3(vertex, POSITION);
+            let position = transformation
+                .transform_point(&Point3::from(position))
+                .coords;
+            self.set_floats(vertex, POSITION, position.as_slice());
+            let normal = (normal_matrix * self.vector3(vertex, NORMAL)).normalize();
+            self.set_floats(vertex, NORMAL, normal.as_slice());
+        }
+        self.data
+            .indices
+            .extend(mesh.indices.iter().map(|index| index + offset));
+    }
+
+    /// Replace the normals by the average of the normals of the triangles around each vertex,
+    /// weighted by their area. Vertices outside of any triangle keep theirs.
+    pub fn compute_normals(&mut self) {
+        let positions = self.data.positions();
+        let mut normals = vec![Vector3::zeros(); positions.len()];
+        for triangle in self.data.indices.chunks_exact(3) {
+            let corner = |i: usize| positions[triangle[i] as usize];
+            // the cross product is as long as twice the area
+            let normal = (corner(1) - corner(0)).cross(&(corner(2) - corner(0)));
+            for &index in triangle {
+                normals[index as usize] += normal;
+            }
+        }
+        for (vertex, normal) in normals.into_iter().enumerate() {
+            if let Some(normal) = normal.try_normalize(1e-12) {
+                self.set_floats(vertex as u32, NORMAL, normal.as_slice());
+            }
+        }
+    }
+
+    pub fn build(self) -> MeshData {
+        self.data
+    }
+
+    fn next_index(&self) -> u32 {
+        u32::try_from(self.data.vertex_count()).expect("too many vertices for 32-bit indices")
+    }
+
+    fn vector3(&self, vertex: u32, name: &str) -> Vector3<f32> {
+        self.data
+            .attribute(name)
+            .map_or_else(Vector3::zeros, |stream| stream.vector3(vertex as usize))
+    }
+}
+
+/// Transformation of the normals that keeps them perpendicular to the transformed surface.
+fn normal_matrix(transformation: &Matrix4<f32>) -> Matrix3<f32> {
+    let linear = transformation.fixed_slice::<3, 3>(0, 0).into_owned();
+    linear
+        .try_inverse()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(linear)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn appended_meshes_are_transformed_and_reindexed() {
+        let mut builder = MeshBuilder::new();
+        builder.append(&MeshData::quad(), &Matrix4::identity());
+        let stretch = Matrix4::new_translation(&Vector3::new(5.0, 0.0, 0.0))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.0, 1.0))
+            * Rotation3::new(Vector3::x() * FRAC_PI_2).to_homogeneous();
+        builder.append(&MeshData::quad(), &stretch);
+        let data = builder.build();
+
+        assert_eq!(&data.indices[6..], &[4, 5, 6, 4, 6, 7]);
+        assert_near(data.positions()[6], Vector3::new(6.0, 0.0, 1.0));
+        assert_near(
+            data.attribute(NORMAL).unwrap().vector3(4),
+            Vector3::new(0.0, -1.0, 0.0),
+        );
+        let uv = data.attribute(TEXTURE_COORD).unwrap().value(6).to_vec();
+        assert_eq!(
+            uv,
+            [1.0f32, 1.0]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn cube_faces_point_outwards() {
+        let cube = MeshData::cube();
+        let bounds = cube.bounds();
+
+        assert_eq!(cube.vertex_count(), 24);
+        assert_eq!(cube.indices.len(), 36);
+        assert_near(bounds.min, Vector3::new(-1.0, -1.0, -1.0));
+        assert_near(bounds.max, Vector3::new(1.0, 1.0, 1.0));
+        let normals = cube.attribute(NORMAL).unwrap();
+        for (vertex, position) in cube.positions().iter().enumerate() {
+            // each corner of a face is one unit along its normal
+            assert!((position.dot(&normals.vector3(vertex)) - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn split_parts_keep_every_triangle_within_the_vertex_limit() {
+        let text = MeshData::text("abcde");
+        let parts = text.split(9);
+
+        // two glyphs of four vertices fit in each part
+        let sizes: Vec<_> = parts.iter().map(|part| part.vertex_count()).collect();
+        assert_eq!(sizes, vec![8, 8, 4]);
+        assert_eq!(parts[2].indices, vec![0, 1, 2, 0, 2, 3]);
+        let triangles = |data: &MeshData| -> Vec<[Vector3<f32>; 3]> {
+            let positions = data.positions();
+            data.indices
+                .chunks_exact(3)
+                .map(|t| [0, 1, 2].map(|i| positions[t[i] as usize]))
+                .collect()
+        };
+        let rejoined: Vec<_> = parts.iter().flat_map(triangles).collect();
+        assert_eq!(rejoined, triangles(&text));
+    }
+
+    #[test]
+    fn computed_normals_follow_the_winding() {
+        let mut builder = MeshBuilder::new();
+        let vertex = |builder: &mut MeshBuilder, x, y| {
+            builder.push_vertex(Vector3::new(x, y, 0.0), Vector2::zeros(), Vector3::x())
+        };
+        let a = vertex(&mut builder, 0.0, 0.0);
+        let b = vertex(&mut builder, 0.0, 1.0);
+        let c = vertex(&mut builder, 1.0, 0.0);
+        let lonely = vertex(&mut builder, 5.0, 5.0);
+        builder.push_triangle(a, b, c);
+
+        builder.compute_normals();
+        let data = builder.build();
+
+        let normals = data.attribute(NORMAL).unwrap();
+        assert_eq!(normals.vector3(a as usize), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(normals.vector3(lonely as usize), Vector3::x());
+    }
+
+    #[test]
+    fn extra_attributes_are_carried_through_append_and_split() {
+        let mut builder =
+            MeshBuilder::new().attribute("aVertexColor", 3, AttributeType::UnsignedByte, true);
+        builder.append(&MeshData::quad(), &Matrix4::identity());
+        builder.set_attribute(2, "aVertexColor", &[255, 128, 0]);
+        builder.append(&MeshData::quad(), &Matrix4::new_translation(&Vector3::x()));
+        let data = builder.build();
+
+        let colors = data.attribute("aVertexColor").unwrap();
+        assert_eq!(colors.vertex_count(), 8);
+        assert_eq!(colors.value(2), &[255, 128, 0]);
+        assert_eq!(colors.value(6), &[0, 0, 0]);
+
+        let parts = data.split(4);
+        assert_eq!(parts.len(), 2);
+        let colors = parts[0].attribute("aVertexColor").unwrap();
+        assert_eq!(colors.vertex_count(), 4);
+        assert_eq!(colors.value(2), &[255, 128, 0]);
+        assert_near(parts[1].positions()[0], Vector3::new(0.0, -1.0, 0.0));
+    }
+}
@@ -0,0 +1,696 @@
+use crate::actions::{Action, Bindings};
+use crate::camera::{Camera, Projection};
+use crate::document;
+use crate::error::RendererError;
+use js_sys::{Array, Function, Object, Reflect};
+use nalgebra::Vector3;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlCanvasElement;
+
+/// Where the renderer should draw.
+#[derive(Clone)]
+pub enum CanvasTarget {
+    Id(String),
+    Element(HtmlCanvasElement),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EntityKind {
+    Quad,
+    Cube,
+    Text(String),
+}
+
+/// When frames are drawn.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RenderMode {
+    /// Draw at every animation frame.
+    Continuous,
+    /// Draw only after something changed: input, camera, entities, a loaded texture, a running
+    /// animation or an explicit `requestRedraw()`.
+    OnDemand,
+}
+
+/// How the vertices of a mesh are laid out in GPU buffers.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VertexFormat {
+    /// All the attributes of a vertex next to each other in a single buffer.
+    Interleaved,
+    /// One buffer per attribute, each bound on its own.
+    Separate,
+}
+
+/// Description of an entity created when the renderer starts.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EntityDescription {
+    pub kind: EntityKind,
+    pub position: Vector3<f32>,
+}
+
+/// Startup options for [`crate::run`].
+///
+/// From JS either build it field by field or pass a plain object to the constructor:
+///
+/// ```js
+/// new RendererOptions({
+///     canvas: "canvas",               // element id or an HTMLCanvasElement
+///     background: [0, 0, 0, 1],
+///     camera: [0, 0, 10],
+///     zoom: 1, minZoom: 0.1, maxZoom: 3.7,   // bigger zooms show more of the scene
+///     zoomSensitivity: 1,             // multiplies the zoom speed of wheels and pinches
+///     panSensitivity: 1,              // multiplies the speed of trackpad scrolls
+///     projection: "orthographic",     // or "perspective", dragging then orbits the camera
+///     panFriction: 4,                 // how fast the camera stops gliding after a drag
+///     bindings: { a: "panLeft", "Ctrl+y": "redo", Home: null },   // null unbinds
+///     texture: "texture/rust_logo.png",
+///     webgl2: true,                   // falls back to WebGL1 when unavailable
+///     renderMode: "onDemand",         // or "continuous"
+///     tickRate: 60,                   // simulation steps per second
+///     statsOverlay: false,            // show frame statistics over the canvas
+///     gpuPicking: false,              // pick with an off-screen pass, for dense scenes
+///     vertexFormat: "interleaved",    // or "separate", one buffer per vertex attribute
+///     snapGrid: 0.5,                  // dragged entities snap to the grid, 0 turns it off
+///     snapToEntities: true,           // and line up with the edges of the other entities
+///     scene: [{ type: "text", text: "Salut la team", position: [0, 0, 0] }],
+///     onError: (error) => console.error(error.kind, error.message),
+///     onClick: (hit) => console.log(hit?.entity),   // a `Hit`, null on empty space
+///     onDoubleClick: (hit) => {},
+///     onHover: (hit) => {},           // when another entity, or none, is under the mouse
+///     onSelectionChange: (entities) => {},   // indices of the selected entities
+///     onMoveEnd: (moves) => {},       // `EntityMove`s of a finished drag, with `from` and `to`
+///     onHistoryChange: (canUndo, canRedo) => {},
+/// })
+/// ```
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct RendererOptions {
+    pub(crate) canvas: CanvasTarget,
+    pub(crate) background_color: [f32; 4],
+    pub(crate) camera_position: Vector3<f32>,
+    pub(crate) zoom: f32,
+    pub(crate) min_zoom: f32,
+    pub(crate) max_zoom: f32,
+    pub(crate) zoom_sensitivity: f32,
+    pub(crate) pan_sensitivity: f32,
+    pub(crate) projection: Projection,
+    pub(crate) pan_friction: f32,
+    pub(crate) bindings: Bindings,
+    pub(crate) texture_url: String,
+    pub(crate) prefer_webgl2: bool,
+    pub(crate) render_mode: RenderMode,
+    pub(crate) tick_rate: f64,
+    pub(crate) stats_overlay: bool,
+    pub(crate) gpu_picking: bool,
+    pub(crate) vertex_format: VertexFormat,
+    pub(crate) snap_grid: f32,
+    pub(crate) snap_to_entities: bool,
+    pub(crate) scene: Vec<EntityDescription>,
+    /// Receives the errors that happen once the renderer runs, they are logged when unset.
+    pub(crate) on_error: Option<Function>,
+    pub(crate) on_click: Option<Function>,
+    pub(crate) on_double_click: Option<Function>,
+    pub(crate) on_hover: Option<Function>,
+    pub(crate) on_selection_change: Option<Function>,
+    pub(crate) on_move_end: Option<Function>,
+    pub(crate) on_history_change: Option<Function>,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        RendererOptions {
+            canvas: CanvasTarget::Id("canvas".to_string()),
+            background_color: [0.0, 0.0, 0.0, 1.0],
+            camera_position: Vector3::new(0.0, 0.0, 10.0),
+            zoom: 1.0,
+            min_zoom: RendererOptions::DEFAULT_ZOOM_LIMITS.0,
+            max_zoom: RendererOptions::DEFAULT_ZOOM_LIMITS.1,
+            zoom_sensitivity: 1.0,
+            pan_sensitivity: 1.0,
+            projection: Projection::Orthographic,
+            pan_friction: 4.0,
+            bindings: Default::default(),
+            texture_url: "http://localhost:8000/texture/rust_logo.png".to_string(),
+            prefer_webgl2: true,
+            render_mode: RenderMode::OnDemand,
+            tick_rate: 60.0,
+            stats_overlay: false,
+            gpu_picking: false,
+            vertex_format: VertexFormat::Interleaved,
+            snap_grid: 0.0,
+            snap_to_entities: false,
+            scene: vec![EntityDescription {
+                kind: EntityKind::Text("Salut la team".to_string()),
+                position: Vector3::zeros(),
+            }],
+            on_error: None,
+            on_click: None,
+            on_double_click: None,
+            on_hover: None,
+            on_selection_change: None,
+            on_move_end: None,
+            on_history_change: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl RendererOptions {
+    /// Build options from a plain JS object, missing keys keep their default value.
+    #[wasm_bindgen(constructor)]
+    pub fn new(value: JsValue) -> Result<RendererOptions, RendererError> {
+        let mut options = RendererOptions::default();
+        if value.is_undefined() || value.is_null() {
+            return Ok(options);
+        }
+
+        if let Some(canvas) = property(&value, "canvas")? {
+            options.canvas = if let Some(id) = canvas.as_string() {
+                CanvasTarget::Id(id)
+            } else {
+                CanvasTarget::Element(
+                    canvas
+                        .dyn_into::<HtmlCanvasElement>()
+                        .map_err(|_| invalid("`canvas` must be an id or a canvas"))?,
+                )
+            };
+        }
+        if let Some(background) = property(&value, "background")? {
+            let color = numbers(&background, "background")?;
+            options.background_color = match color.as_slice() {
+                [r, g, b] => [*r, *g, *b, 1.0],
+                [r, g, b, a] => [*r, *g, *b, *a],
+                _ => return Err(invalid("`background` expects 3 or 4 numbers")),
+            };
+        }
+        if let Some(camera) = property(&value, "camera")? {
+            let position = vector3(&camera, "camera", options.camera_position.z)?;
+            options.set_camera_position(position.x, position.y, position.z)?;
+        }
+        let min_zoom = match property(&value, "minZoom")? {
+            Some(min_zoom) => number(&min_zoom, "minZoom")?,
+            None => options.min_zoom,
+        };
+        let max_zoom = match property(&value, "maxZoom")? {
+            Some(max_zoom) => number(&max_zoom, "maxZoom")?,
+            None => options.max_zoom,
+        };
+        options.set_zoom_limits(min_zoom, max_zoom)?;
+        if let Some(zoom) = property(&value, "zoom")? {
+            options.set_zoom(number(&zoom, "zoom")?)?;
+        }
+        if let Some(sensitivity) = property(&value, "zoomSensitivity")? {
+            options.set_zoom_sensitivity(number(&sensitivity, "zoomSensitivity")?)?;
+        }
+        if let Some(sensitivity) = property(&value, "panSensitivity")? {
+            options.set_pan_sensitivity(number(&sensitivity, "panSensitivity")?)?;
+        }
+        if let Some(projection) = property(&value, "projection")? {
+            options.projection = match projection.as_string().as_deref() {
+                Some("orthographic") => Projection::Orthographic,
+                Some("perspective") => Projection::Perspective,
+                _ => {
+                    return Err(invalid(
+                        "`projection` must be \"orthographic\" or \"perspective\"",
+                    ))
+                }
+            };
+        }
+        if let Some(pan_friction) = property(&value, "panFriction")? {
+            options.set_pan_friction(number(&pan_friction, "panFriction")?)?;
+        }
+        if let Some(bindings) = property(&value, "bindings")? {
+            for entry in Object::entries(&Object::from(bindings)).iter() {
+                let entry = Array::from(&entry);
+                let key = entry.get(0).as_string().unwrap_or_default();
+                let action = entry.get(1);
+                if action.is_null() || action.is_undefined() {
+                    options.bindings.unbind(&key);
+                    continue;
+                }
+                let action = action
+                    .as_string()
+                    .and_then(|name| Action::from_name(&name))
+                    .ok_or_else(|| {
+                        RendererError::InvalidOptions(format!("unknown action bound to `{key}`"))
+                    })?;
+                options.bindings.bind(key, action);
+            }
+        }
+        if let Some(texture) = property(&value, "texture")? {
+            options.texture_url = texture
+                .as_string()
+                .ok_or_else(|| invalid("`texture` must be a string"))?;
+        }
+        if let Some(webgl2) = property(&value, "webgl2")? {
+            options.prefer_webgl2 = webgl2
+                .as_bool()
+                .ok_or_else(|| invalid("`webgl2` must be a boolean"))?;
+        }
+        if let Some(render_mode) = property(&value, "renderMode")? {
+            options.render_mode = match render_mode.as_string().as_deref() {
+                Some("continuous") => RenderMode::Continuous,
+                Some("onDemand") => RenderMode::OnDemand,
+                _ => {
+                    return Err(invalid(
+                        "`renderMode` must be \"continuous\" or \"onDemand\"",
+                    ))
+                }
+            };
+        }
+        if let Some(tick_rate) = property(&value, "tickRate")? {
+            options.set_tick_rate(number(&tick_rate, "tickRate")? as f64)?;
+        }
+        if let Some(stats_overlay) = property(&value, "statsOverlay")? {
+            options.stats_overlay = stats_overlay
+                .as_bool()
+                .ok_or_else(|| invalid("`statsOverlay` must be a boolean"))?;
+        }
+        if let Some(gpu_picking) = property(&value, "gpuPicking")? {
+            options.gpu_picking = gpu_picking
+                .as_bool()
+                .ok_or_else(|| invalid("`gpuPicking` must be a boolean"))?;
+        }
+        if let Some(vertex_format) = property(&value, "vertexFormat")? {
+            options.vertex_format = match vertex_format.as_string().as_deref() {
+                Some("interleaved") => VertexFormat::Interleaved,
+                Some("separate") => VertexFormat::Separate,
+                _ => {
+                    return Err(invalid(
+                        "`vertexFormat` must be \"interleaved\" or \"separate\"",
+                    ))
+                }
+            };
+        }
+        if let Some(snap_grid) = property(&value, "snapGrid")? {
+            options.set_snap_grid(number(&snap_grid, "snapGrid")?)?;
+        }
+        if let Some(snap_to_entities) = property(&value, "snapToEntities")? {
+            options.snap_to_entities = snap_to_entities
+                .as_bool()
+                .ok_or_else(|| invalid("`snapToEntities` must be a boolean"))?;
+        }
+        if let Some(scene) = property(&value, "scene")? {
+            if !Array::is_array(&scene) {
+                return Err(invalid("`scene` must be an array"));
+            }
+            options.scene = Array::from(&scene)
+                .iter()
+                .map(|entity| entity_description(&entity))
+                .collect::<Result<_, _>>()?;
+        }
+
+        options.on_error = callback(&value, "onError")?;
+        options.on_click = callback(&value, "onClick")?;
+        options.on_double_click = callback(&value, "onDoubleClick")?;
+        options.on_hover = callback(&value, "onHover")?;
+        options.on_selection_change = callback(&value, "onSelectionChange")?;
+        options.on_move_end = callback(&value, "onMoveEnd")?;
+        options.on_history_change = callback(&value, "onHistoryChange")?;
+
+        Ok(options)
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_canvas_id(&mut self, id: String) {
+        self.canvas = CanvasTarget::Id(id);
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_canvas(&mut self, canvas: HtmlCanvasElement) {
+        self.canvas = CanvasTarget::Element(canvas);
+    }
+
+    pub fn set_background_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.background_color = [r, g, b, a];
+    }
+
+    /// The camera looks down from `z`, which must leave the z = 0 plane in front of its far
+    /// plane.
+    pub fn set_camera_position(&mut self, x: f32, y: f32, z: f32) -> Result<(), RendererError> {
+        if !(x.is_finite() && y.is_finite() && z > 0.0 && z < Camera::Z_FAR) {
+            return Err(RendererError::InvalidOptions(format!(
+                "`camera` must be finite with 0 < z < {}",
+                Camera::Z_FAR
+            )));
+        }
+        self.camera_position = Vector3::new(x, y, z);
+        Ok(())
+    }
+
+    /// Zoom range of the camera, 0.1 to 3.7 by default.
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) -> Result<(), RendererError> {
+        check_zoom_limits(min, max)?;
+        self.min_zoom = min;
+        self.max_zoom = max;
+        Ok(())
+    }
+
+    /// Multiplies how fast mouse wheels and trackpad pinches zoom, 1 by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_zoom_sensitivity(&mut self, sensitivity: f32) -> Result<(), RendererError> {
+        self.zoom_sensitivity = positive(sensitivity, "zoomSensitivity")?;
+        Ok(())
+    }
+
+    /// Multiplies how fast two finger trackpad scrolls pan, 1 by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_pan_sensitivity(&mut self, sensitivity: f32) -> Result<(), RendererError> {
+        self.pan_sensitivity = positive(sensitivity, "panSensitivity")?;
+        Ok(())
+    }
+
+    /// Starting zoom, within the zoom limits.
+    #[wasm_bindgen(setter)]
+    pub fn set_zoom(&mut self, zoom: f32) -> Result<(), RendererError> {
+        if !(zoom >= self.min_zoom && zoom <= self.max_zoom) {
+            return Err(RendererError::InvalidOptions(format!(
+                "`zoom` must be within {} and {}",
+                self.min_zoom, self.max_zoom
+            )));
+        }
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// Orthographic by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Rate at which the camera slows down once a drag is released, per second. Higher values
+    /// stop it sooner.
+    #[wasm_bindgen(setter)]
+    pub fn set_pan_friction(&mut self, pan_friction: f32) -> Result<(), RendererError> {
+        if !(pan_friction > 0.0 && pan_friction.is_finite()) {
+            return Err(invalid("`panFriction` must be a positive number"));
+        }
+        self.pan_friction = pan_friction;
+        Ok(())
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_texture_url(&mut self, url: String) {
+        self.texture_url = url;
+    }
+
+    /// Use a WebGL2 context when the browser has one, on by default. WebGL1 is used otherwise.
+    #[wasm_bindgen(setter)]
+    pub fn set_webgl2(&mut self, prefer_webgl2: bool) {
+        self.prefer_webgl2 = prefer_webgl2;
+    }
+
+    /// Only draw when something changed, on by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// Number of simulation steps per second, independent from the display refresh rate.
+    #[wasm_bindgen(setter)]
+    pub fn set_tick_rate(&mut self, tick_rate: f64) -> Result<(), RendererError> {
+        if !(tick_rate > 0.0 && tick_rate.is_finite()) {
+            return Err(invalid("`tickRate` must be a positive number"));
+        }
+        self.tick_rate = tick_rate;
+        Ok(())
+    }
+
+    /// Show the frame statistics over the canvas, off by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_stats_overlay(&mut self, stats_overlay: bool) {
+        self.stats_overlay = stats_overlay;
+    }
+
+    /// Find the entity under the cursor by drawing the scene off-screen with one color per
+    /// entity, instead of testing the triangles of every entity. Off by default, it pays off
+    /// with thousands of entities.
+    #[wasm_bindgen(setter)]
+    pub fn set_gpu_picking(&mut self, gpu_picking: bool) {
+        self.gpu_picking = gpu_picking;
+    }
+
+    /// Upload each mesh in a single interleaved vertex buffer, the default, or in one buffer
+    /// per attribute.
+    #[wasm_bindgen(setter)]
+    pub fn set_vertex_format(&mut self, vertex_format: VertexFormat) {
+        self.vertex_format = vertex_format;
+    }
+
+    /// Spacing of the grid dragged entities snap to, 0 (the default) moves them freely.
+    #[wasm_bindgen(setter)]
+    pub fn set_snap_grid(&mut self, snap_grid: f32) -> Result<(), RendererError> {
+        if !(snap_grid >= 0.0 && snap_grid.is_finite()) {
+            return Err(invalid("`snapGrid` must be a positive number or 0"));
+        }
+        self.snap_grid = snap_grid;
+        Ok(())
+    }
+
+    /// Line the edges of dragged entities up with the edges of the other entities nearby, off
+    /// by default.
+    #[wasm_bindgen(setter)]
+    pub fn set_snap_to_entities(&mut self, snap_to_entities: bool) {
+        self.snap_to_entities = snap_to_entities;
+    }
+
+    /// Called with a `RendererError` JS error, its `kind` property tells what failed.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_error(&mut self, callback: Function) {
+        self.on_error = Some(callback);
+    }
+
+    /// Called with the `Hit` under a click, or null when nothing was clicked.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_click(&mut self, callback: Function) {
+        self.on_click = Some(callback);
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_on_double_click(&mut self, callback: Function) {
+        self.on_double_click = Some(callback);
+    }
+
+    /// Called with the `Hit` under the mouse when another entity, or none, is hovered.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_hover(&mut self, callback: Function) {
+        self.on_hover = Some(callback);
+    }
+
+    /// Called with the indices of the selected entities whenever the selection changes.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_selection_change(&mut self, callback: Function) {
+        self.on_selection_change = Some(callback);
+    }
+
+    /// Called with an array of `EntityMove` when a drag of entities ends, one for each
+    /// entity that moved.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_move_end(&mut self, callback: Function) {
+        self.on_move_end = Some(callback);
+    }
+
+    /// Called with whether undo and redo are possible, whenever that changes.
+    #[wasm_bindgen(setter)]
+    pub fn set_on_history_change(&mut self, callback: Function) {
+        self.on_history_change = Some(callback);
+    }
+
+    /// Run `action` when `key`, a `KeyboardEvent.key` value after its `Ctrl+`, `Alt+` or
+    /// `Shift+` modifiers like `Ctrl+Shift+z`, is pressed on the canvas.
+    pub fn bind_key(&mut self, key: String, action: Action) {
+        self.bindings.bind(key, action);
+    }
+
+    pub fn unbind_key(&mut self, key: &str) {
+        self.bindings.unbind(key);
+    }
+
+    /// Remove every entity of the initial scene.
+    pub fn clear_scene(&mut self) {
+        self.scene.clear();
+    }
+
+    pub fn add_quad(&mut self, x: f32, y: f32, z: f32) {
+        self.push_entity(EntityKind::Quad, x, y, z);
+    }
+
+    pub fn add_cube(&mut self, x: f32, y: f32, z: f32) {
+        self.push_entity(EntityKind::Cube, x, y, z);
+    }
+
+    pub fn add_text(&mut self, text: String, x: f32, y: f32, z: f32) {
+        self.push_entity(EntityKind::Text(text), x, y, z);
+    }
+}
+
+impl RendererOptions {
+    pub(crate) const DEFAULT_ZOOM_LIMITS: (f32, f32) = (0.1, 3.7);
+
+    fn push_entity(&mut self, kind: EntityKind, x: f32, y: f32, z: f32) {
+        self.scene.push(EntityDescription {
+            kind,
+            position: Vector3::new(x, y, z),
+        });
+    }
+
+    pub(crate) fn canvas_element(&self) -> Result<HtmlCanvasElement, RendererError> {
+        match &self.canvas {
+            CanvasTarget::Element(canvas) => Ok(canvas.clone()),
+            CanvasTarget::Id(id) => document()
+                .get_element_by_id(id)
+                .and_then(|element| element.dyn_into::<HtmlCanvasElement>().ok())
+                .ok_or_else(|| RendererError::CanvasNotFound(id.clone())),
+        }
+    }
+}
+
+fn entity_description(value: &JsValue) -> Result<EntityDescription, RendererError> {
+    let kind = property(value, "type")?
+        .and_then(|kind| kind.as_string())
+        .ok_or_else(|| invalid("scene entities need a `type`"))?;
+    let kind = match kind.as_str() {
+        "quad" => EntityKind::Quad,
+        "cube" => EntityKind::Cube,
+        "text" => EntityKind::Text(
+            property(value, "text")?
+                .and_then(|text| text.as_string())
+                .ok_or_else(|| invalid("text entities need a `text`"))?,
+        ),
+        other => {
+            return Err(RendererError::InvalidOptions(format!(
+                "unknown entity type `{other}`"
+            )))
+        }
+    };
+    let position = match property(value, "position")? {
+        Some(position) => vector3(&position, "position", 0.0)?,
+        None => Vector3::zeros(),
+    };
+    Ok(EntityDescription { kind, position })
+}
+
+/// Zooms above `Camera::MAX_ZOOM` would widen the orthographic view to a half turn.
+pub(crate) fn check_zoom_limits(min: f32, max: f32) -> Result<(), RendererError> {
+    if !(min > 0.0 && min <= max && max <= Camera::MAX_ZOOM) {
+        return Err(RendererError::InvalidOptions(format!(
+            "zoom limits must satisfy 0 < `minZoom` <= `maxZoom` <= {}",
+            Camera::MAX_ZOOM
+        )));
+    }
+    Ok(())
+}
+
+fn positive(value: f32, name: &str) -> Result<f32, RendererError> {
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err(RendererError::InvalidOptions(format!(
+            "`{name}` must be a positive number"
+        )))
+    }
+}
+
+fn invalid(message: &str) -> RendererError {
+    RendererError::InvalidOptions(message.to_string())
+}
+
+fn property(object: &JsValue, key: &str) -> Result<Option<JsValue>, RendererError> {
+    let value =
+        Reflect::get(object, &JsValue::from_str(key)).map_err(|_| invalid("expected an object"))?;
+    if value.is_undefined() || value.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(value))
+    }
+}
+
+fn callback(object: &JsValue, key: &str) -> Result<Option<Function>, RendererError> {
+    match property(object, key)? {
+        Some(value) => value
+            .dyn_into::<Function>()
+            .map(Some)
+            .map_err(|_| RendererError::InvalidOptions(format!("`{key}` must be a function"))),
+        None => Ok(None),
+    }
+}
+
+fn number(value: &JsValue, name: &str) -> Result<f32, RendererError> {
+    value
+        .as_f64()
+        .map(|value| value as f32)
+        .ok_or_else(|| RendererError::InvalidOptions(format!("`{name}` must be a number")))
+}
+
+fn numbers(value: &JsValue, name: &str) -> Result<Vec<f32>, RendererError> {
+    if !Array::is_array(value) {
+        return Err(RendererError::InvalidOptions(format!(
+            "`{name}` must be an array"
+        )));
+    }
+    Array::from(value)
+        .iter()
+        .map(|value| number(&value, name))
+        .collect()
+}
+
+/// Read `[x, y]` or `[x, y, z]`, a missing z takes `default_z`.
+fn vector3(value: &JsValue, name: &str, default_z: f32) -> Result<Vector3<f32>, RendererError> {
+    match numbers(value, name)?.as_slice() {
+        [x, y] => Ok(Vector3::new(*x, *y, default_z)),
+        [x, y, z] => Ok(Vector3::new(*x, *y, *z)),
+        _ => Err(RendererError::InvalidOptions(format!(
+            "`{name}` expects 2 or 3 numbers"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_must_look_at_the_scene_from_within_the_far_plane() {
+        let mut options = RendererOptions::default();
+        for z in [0.0, -5.0, Camera::Z_FAR, 250.0, f32::NAN, f32::INFINITY] {
+            assert!(matches!(
+                options.set_camera_position(0.0, 0.0, z),
+                Err(RendererError::InvalidOptions(_))
+            ));
+        }
+        assert!(options.set_camera_position(f32::NAN, 0.0, 10.0).is_err());
+        assert_eq!(options.camera_position, Vector3::new(0.0, 0.0, 10.0));
+
+        options.set_camera_position(3.0, -2.0, 99.0).unwrap();
+        assert_eq!(options.camera_position, Vector3::new(3.0, -2.0, 99.0));
+    }
+
+    #[test]
+    fn zoom_must_be_within_the_zoom_limits() {
+        let mut options = RendererOptions::default();
+        for zoom in [
+            0.0,
+            0.05,
+            3.8,
+            -1.0,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+        ] {
+            assert!(matches!(
+                options.set_zoom(zoom),
+                Err(RendererError::InvalidOptions(_))
+            ));
+        }
+        assert_eq!(options.zoom, 1.0);
+
+        options.set_zoom(3.7).unwrap();
+        assert_eq!(options.zoom, 3.7);
+        options.set_zoom_limits(0.5, 2.0).unwrap();
+        assert!(options.set_zoom(3.0).is_err());
+        options.set_zoom(0.5).unwrap();
+    }
+}
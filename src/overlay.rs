@@ -0,0 +1,139 @@
+use crate::document;
+use crate::stats::FrameStats;
+use nalgebra::Vector2;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, HtmlElement};
+
+/// Frame statistics shown over the top left corner of the canvas.
+pub(crate) struct StatsOverlay {
+    element: HtmlElement,
+    last_refresh: f64,
+}
+
+impl StatsOverlay {
+    /// Rewriting the text at every frame would cost more layout time than the scene itself.
+    const REFRESH_INTERVAL: f64 = 250.0;
+
+    /// `None` when the canvas is not in the document.
+    pub fn new(canvas: &HtmlCanvasElement) -> Option<StatsOverlay> {
+        let element = document()
+            .create_element("div")
+            .ok()?
+            .dyn_into::<HtmlElement>()
+            .ok()?;
+        // as a sibling of the canvas the overlay shares its offset parent
+        canvas
+            .insert_adjacent_element("afterend", &element)
+            .ok()??;
+        let style = element.style();
+        for (property, value) in [
+            ("position", "absolute"),
+            ("pointer-events", "none"),
+            ("white-space", "pre"),
+            ("padding", "4px 8px"),
+            ("background-color", "rgba(0, 0, 0, 0.6)"),
+            ("color", "white"),
+            ("font", "12px monospace"),
+        ] {
+            style.set_property(property, value).ok()?;
+        }
+        Some(StatsOverlay {
+            element,
+            last_refresh: f64::NEG_INFINITY,
+        })
+    }
+
+    pub fn show(&mut self, canvas: &HtmlCanvasElement, stats: &FrameStats, timestamp: f64) {
+        if timestamp - self.last_refresh < Self::REFRESH_INTERVAL {
+            return;
+        }
+        self.last_refresh = timestamp;
+        let style = self.element.style();
+        let _ = style.set_property("left", &format!("{}px", canvas.offset_left()));
+        let _ = style.set_property("top", &format!("{}px", canvas.offset_top()));
+        self.element.set_text_content(Some(&stats.to_string()));
+    }
+}
+
+impl Drop for StatsOverlay {
+    fn drop(&mut self) {
+        self.element.remove();
+    }
+}
+
+/// Rubber band of a box selection, drawn over the canvas. The band lies on the z = 0 plane, so
+/// it is a polygon on screen once the view is turned or tilted.
+pub(crate) struct SelectionBoxOverlay {
+    element: HtmlElement,
+    /// Corners last shown, the SVG is only rewritten when they move.
+    corners: Option<[Vector2<f32>; 4]>,
+}
+
+impl SelectionBoxOverlay {
+    /// `None` when the canvas is not in the document.
+    pub fn new(canvas: &HtmlCanvasElement) -> Option<SelectionBoxOverlay> {
+        let element = document()
+            .create_element("div")
+            .ok()?
+            .dyn_into::<HtmlElement>()
+            .ok()?;
+        canvas
+            .insert_adjacent_element("afterend", &element)
+            .ok()??;
+        let style = element.style();
+        for (property, value) in [
+            ("position", "absolute"),
+            ("pointer-events", "none"),
+            ("display", "none"),
+        ] {
+            style.set_property(property, value).ok()?;
+        }
+        Some(SelectionBoxOverlay {
+            element,
+            corners: None,
+        })
+    }
+
+    /// Show the band with its corners in canvas pixels, or hide it.
+    pub fn show(&mut self, canvas: &HtmlCanvasElement, corners: Option<[Vector2<f32>; 4]>) {
+        if corners == self.corners {
+            return;
+        }
+        self.corners = corners;
+        let style = self.element.style();
+        let corners = match corners {
+            Some(corners) => corners,
+            None => {
+                let _ = style.set_property("display", "none");
+                return;
+            }
+        };
+        let (width, height) = (canvas.client_width(), canvas.client_height());
+        for (property, value) in [
+            ("display", "block".to_string()),
+            ("left", format!("{}px", canvas.offset_left())),
+            ("top", format!("{}px", canvas.offset_top())),
+            ("width", format!("{}px", width)),
+            ("height", format!("{}px", height)),
+        ] {
+            let _ = style.set_property(property, &value);
+        }
+        let points: Vec<_> = corners
+            .iter()
+            .map(|corner| format!("{},{}", corner.x, corner.y))
+            .collect();
+        self.element.set_inner_html(&format!(
+            "<svg width=\"{}\" height=\"{}\"><polygon points=\"{}\" \
+             fill=\"rgba(80, 140, 255, 0.15)\" stroke=\"rgb(80, 140, 255)\"/></svg>",
+            width,
+            height,
+            points.join(" ")
+        ));
+    }
+}
+
+impl Drop for SelectionBoxOverlay {
+    fn drop(&mut self) {
+        self.element.remove();
+    }
+}
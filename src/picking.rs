@@ -0,0 +1,438 @@
+use crate::backend::{Backend, FramebufferId, ProgramId, UniformValue};
+use crate::camera::{Camera, Ray};
+use crate::error::RendererError;
+use crate::primitive::Primitive;
+use nalgebra::{Matrix4, Point3, Vector2, Vector3};
+use wasm_bindgen::prelude::*;
+
+/// Entity under a screen position.
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hit {
+    /// Index of the entity in the scene.
+    pub entity: usize,
+    /// Hit position in the coordinates of the entity mesh, before its transformation.
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    /// World units from the camera near plane, the closest hit is on top.
+    pub distance: f32,
+}
+
+/// Axis aligned box around a mesh, in its own coordinates.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Bounds {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Bounds {
+    /// Smallest box around the points, empty meshes get an empty box at the origin.
+    pub fn around(points: &[Vector3<f32>]) -> Bounds {
+        match points.split_first() {
+            Some((first, rest)) => rest.iter().fold(
+                Bounds {
+                    min: *first,
+                    max: *first,
+                },
+                |bounds, point| Bounds {
+                    min: bounds.min.inf(point),
+                    max: bounds.max.sup(point),
+                },
+            ),
+            None => Bounds {
+                min: Vector3::zeros(),
+                max: Vector3::zeros(),
+            },
+        }
+    }
+
+    /// Box around this one once transformed by `matrix`.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Bounds {
+        let corners: Vec<_> = (0..8)
+            .map(|corner: usize| {
+                let pick = |axis: usize| {
+                    if corner & (1 << axis) == 0 {
+                        self.min[axis]
+                    } else {
+                        self.max[axis]
+                    }
+                };
+                let corner = Point3::new(pick(0), pick(1), pick(2));
+                matrix.transform_point(&corner).coords
+            })
+            .collect();
+        Bounds::around(&corners)
+    }
+
+    /// Slab test, flat boxes like the one of a quad are still hit.
+    pub fn is_hit(&self, ray: &Ray) -> bool {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let (origin, direction) = (ray.origin[axis], ray.direction[axis]);
+            let (min, max) = (self.min[axis], self.max[axis]);
+            if direction.abs() < 1e-12 {
+                if origin < min || origin > max {
+                    return false;
+                }
+                continue;
+            }
+            let (a, b) = ((min - origin) / direction, (max - origin) / direction);
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Distance along the ray to the triangle, both faces count. Möller–Trumbore.
+pub(crate) fn intersect_triangle(ray: &Ray, triangle: [Vector3<f32>; 3]) -> Option<f32> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = ray.direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - triangle[0];
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(&q) * inverse;
+    (distance >= 0.0).then_some(distance)
+}
+
+/// Off-screen pass drawing every entity with its id as color, the pixel under the cursor then
+/// tells which one is on top without testing the triangles of the whole scene.
+pub(crate) struct PickingPass {
+    program: ProgramId,
+    /// With its size, created at the first pick and again when the viewport changes.
+    framebuffer: Option<(FramebufferId, i32, i32)>,
+    stale: bool,
+    /// Clear color of the scene, to put back after clearing with the background id.
+    background_color: [f32; 4],
+}
+
+impl PickingPass {
+    pub fn new(
+        backend: &dyn Backend,
+        background_color: [f32; 4],
+    ) -> Result<PickingPass, RendererError> {
+        let program = backend.create_program(
+            include_str!("picking_vs.glsl"),
+            include_str!("picking_fs.glsl"),
+        )?;
+        Ok(PickingPass {
+            program,
+            framebuffer: None,
+            stale: true,
+            background_color,
+        })
+    }
+
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Index in `entities` of the entity drawn at a canvas position.
+    pub fn entity_at(
+        &mut self,
+        backend: &dyn Backend,
+        camera: &Camera,
+        entities: &[Primitive],
+        alpha: f32,
+        screen: Vector2<f32>,
+    ) -> Result<Option<usize>, RendererError> {
+        let (width, height) = (camera.viewport_width(), camera.viewport_height());
+        let (x, y) = (screen.x.floor() as i32, screen.y.floor() as i32);
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return Ok(None);
+        }
+
+        let framebuffer = match self.framebuffer {
+            Some((framebuffer, w, h)) if (w, h) == (width, height) => framebuffer,
+            previous => {
+                if let Some((framebuffer, _, _)) = previous {
+                    backend.delete_framebuffer(framebuffer);
+                }
+                self.framebuffer = None;
+                let framebuffer = backend.create_framebuffer(width, height)?;
+                self.framebuffer = Some((framebuffer, width, height));
+                self.stale = true;
+                framebuffer
+            }
+        };
+
+        backend.bind_framebuffer(Some(framebuffer));
+        let drawn = if self.stale {
+            self.draw(backend, camera, entities, alpha)
+        } else {
+            Ok(())
+        };
+        // rows of the framebuffer go up from the bottom of the canvas
+        let pixel = drawn.map(|()| backend.read_pixel(x, height - 1 - y));
+        backend.bind_framebuffer(None);
+        self.stale = pixel.is_err();
+
+        Ok(entity_from_pixel(pixel?).filter(|&entity| entity < entities.len()))
+    }
+
+    fn draw(
+        &self,
+        backend: &dyn Backend,
+        camera: &Camera,
+        entities: &[Primitive],
+        alpha: f32,
+    ) -> Result<(), RendererError> {
+        backend.viewport(camera.viewport_width(), camera.viewport_height());
+        backend.set_clear_color([0.0; 4]);
+        backend.clear();
+        backend.set_clear_color(self.background_color);
+
+        backend.use_program(self.program);
+        backend.set_uniform(
+            self.program,
+            "uProjectionMatrix",
+            UniformValue::Mat4(camera.projection_matrix()),
+        )?;
+        backend.set_uniform(
+            self.program,
+            "uModelViewMatrix",
+            UniformValue::Mat4(camera.view_matrix()),
+        )?;
+        for (index, entity) in entities.iter().enumerate() {
+            backend.set_uniform(
+                self.program,
+                "uPickingColor",
+                UniformValue::Vec4(id_color(index)),
+            )?;
+            entity.draw_with(backend, self.program, alpha)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, backend: &dyn Backend) {
+        if let Some((framebuffer, _, _)) = self.framebuffer.take() {
+            backend.delete_framebuffer(framebuffer);
+        }
+        backend.delete_program(self.program);
+    }
+}
+
+/// Ids start at 1, the cleared background is 0.
+fn id_color(index: usize) -> [f32; 4] {
+    let id = index + 1;
+    let byte = |shift: usize| ((id >> shift) & 0xff) as f32 / 255.0;
+    [byte(0), byte(8), byte(16), 1.0]
+}
+
+fn entity_from_pixel(pixel: [u8; 4]) -> Option<usize> {
+    let id = pixel[0] as usize | (pixel[1] as usize) << 8 | (pixel[2] as usize) << 16;
+    id.checked_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Call, RecordingBackend};
+    use crate::tests::scene;
+    use crate::{process_input, DragMode};
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: Vector3::from(origin),
+            direction: Vector3::from(direction),
+        }
+    }
+
+    #[test]
+    fn id_colors_read_back_as_their_entity() {
+        for index in [0, 1, 254, 255, 65_535, 1_000_000] {
+            let color = id_color(index);
+            let pixel = color.map(|channel| (channel * 255.0).round() as u8);
+            assert_eq!(entity_from_pixel(pixel), Some(index));
+        }
+        assert_eq!(entity_from_pixel([0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn rays_through_flat_bounds_hit_them() {
+        let bounds = Bounds::around(&[
+            Vector3::new(-1.0, -1.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.5, 0.0),
+        ]);
+
+        assert_eq!(bounds.max, Vector3::new(1.0, 1.0, 0.0));
+        assert!(bounds.is_hit(&ray([0.5, 0.5, 10.0], [0.0, 0.0, -1.0])));
+        assert!(!bounds.is_hit(&ray([1.5, 0.5, 10.0], [0.0, 0.0, -1.0])));
+        assert!(!bounds.is_hit(&ray([0.5, 0.5, 10.0], [0.0, 0.0, 1.0])));
+    }
+
+    #[test]
+    fn triangles_are_hit_from_both_sides() {
+        let triangle = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(2.0, 0.0, 1.0),
+            Vector3::new(0.0, 2.0, 1.0),
+        ];
+
+        let front = intersect_triangle(&ray([0.5, 0.5, 5.0], [0.0, 0.0, -2.0]), triangle);
+        let back = intersect_triangle(&ray([0.5, 0.5, -3.0], [0.0, 0.0, 1.0]), triangle);
+
+        assert_eq!(front, Some(2.0));
+        assert_eq!(back, Some(4.0));
+        assert_eq!(
+            intersect_triangle(&ray([1.5, 1.5, 5.0], [0.0, 0.0, -1.0]), triangle),
+            None
+        );
+    }
+
+    #[test]
+    fn pick_finds_the_topmost_entity_under_the_cursor() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(0.0, 0.0, 0.0);
+            options.add_quad(4.0, 0.0, 0.0);
+        });
+        let camera = renderer.camera.clone();
+        let screen = |x, y, z| camera.world_to_screen(Vector3::new(x, y, z));
+
+        // the cube top sits in front of the quad it overlaps
+        let cube = renderer.pick(screen(0.5, 0.5, 1.0)).unwrap();
+        let quad = renderer.pick(screen(4.25, -0.5, 0.0)).unwrap();
+
+        assert_eq!(cube.entity, 1);
+        assert!(
+            (Vector3::new(cube.x, cube.y, cube.z) - Vector3::new(0.5, 0.5, 1.0))
+                .abs()
+                .max()
+                < 1e-3
+        );
+        assert_eq!(quad.entity, 2);
+        assert!(
+            (Vector3::new(quad.x, quad.y, quad.z) - Vector3::new(0.25, -0.5, 0.0))
+                .abs()
+                .max()
+                < 1e-3
+        );
+        assert!(quad.distance > cube.distance);
+        assert_eq!(renderer.pick(Vector2::new(5.0, 5.0)), None);
+    }
+
+    #[test]
+    fn hits_follow_the_entity_transformation() {
+        let (_, mut renderer) = scene(|options| options.add_quad(0.0, 0.0, 0.0));
+        renderer.entities[0].scale = Vector3::new(2.0, 1.0, 1.0);
+        renderer.entities[0].rotation = Vector3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2);
+        renderer.entities[0].snapshot();
+
+        // rotated a quarter turn, the long side of the quad is vertical
+        let hit = renderer
+            .pick(renderer.camera.world_to_screen(Vector3::new(0.0, 1.5, 0.0)))
+            .unwrap();
+
+        assert!((hit.x - 0.75).abs() < 1e-3 && hit.y.abs() < 1e-3);
+        assert_eq!(
+            renderer.pick(renderer.camera.world_to_screen(Vector3::new(1.5, 0.0, 0.0))),
+            None
+        );
+    }
+
+    #[test]
+    fn gpu_picking_reads_the_entity_id_under_the_cursor() {
+        let (backend, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(0.0, 0.0, 0.0);
+            options.set_gpu_picking(true);
+        });
+        let on_cube = renderer.camera.world_to_screen(Vector3::new(0.5, 0.5, 1.0));
+        backend.clear_calls();
+
+        // the pass drew the cube with id 2
+        backend.set_pixel([2, 0, 0, 255]);
+        let hit = renderer.pick(on_cube).unwrap();
+
+        assert_eq!(hit.entity, 1);
+        assert!(
+            (Vector3::new(hit.x, hit.y, hit.z) - Vector3::new(0.5, 0.5, 1.0))
+                .abs()
+                .max()
+                < 1e-3
+        );
+        let calls = backend.calls();
+        let framebuffer = calls.iter().find_map(|call| match call {
+            Call::CreateFramebuffer(framebuffer, 800, 600) => Some(*framebuffer),
+            _ => None,
+        });
+        assert_eq!(calls[1], Call::BindFramebuffer(framebuffer));
+        assert!(calls.contains(&Call::ReadPixel(on_cube.x as i32, 599 - on_cube.y as i32)));
+        assert_eq!(calls.last(), Some(&Call::BindFramebuffer(None)));
+        let draws = calls
+            .iter()
+            .filter(|call| matches!(call, Call::DrawTriangles(_)))
+            .count();
+        assert_eq!(draws, 2);
+
+        backend.set_pixel([0, 0, 0, 0]);
+        assert_eq!(renderer.pick(on_cube), None);
+    }
+
+    #[test]
+    fn gpu_picking_pass_is_only_drawn_after_a_change() {
+        let (backend, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.set_gpu_picking(true);
+        });
+        let draws = |backend: &RecordingBackend| {
+            backend
+                .calls()
+                .iter()
+                .filter(|call| matches!(call, Call::DrawTriangles(_)))
+                .count()
+        };
+
+        renderer.pick(Vector2::new(400.0, 300.0));
+        renderer.pick(Vector2::new(410.0, 300.0));
+        assert_eq!(draws(&backend), 1);
+
+        renderer.camera.translate(Vector3::new(1.0, 0.0, 0.0));
+        renderer.request_redraw();
+        renderer.pick(Vector2::new(410.0, 300.0));
+        assert_eq!(draws(&backend), 2);
+
+        renderer.free();
+        assert_eq!(backend.live_framebuffer_count(), 0);
+    }
+
+    #[test]
+    fn hover_reports_changes_and_clicks_ignore_drags() {
+        let (_, mut renderer) = scene(|options| options.add_quad(0.0, 0.0, 0.0));
+        let on_quad = renderer.camera.world_to_screen(Vector3::new(0.5, 0.0, 0.0));
+
+        assert_eq!(renderer.hover(Some(on_quad)).unwrap().unwrap().entity, 0);
+        assert_eq!(renderer.hover(Some(on_quad + Vector2::new(1.0, 0.0))), None);
+        assert_eq!(renderer.hover(None), Some(None));
+
+        renderer.pointer_down(1, on_quad, DragMode::Pan, false);
+        renderer.pointer_up(1);
+        assert!(renderer.clicked);
+
+        renderer.pointer_down(1, on_quad, DragMode::Pan, false);
+        renderer.pointer_move(1, on_quad + Vector2::new(50.0, 0.0));
+        process_input(&mut renderer, 0.0);
+        renderer.pointer_up(1);
+        assert!(!renderer.clicked);
+    }
+}
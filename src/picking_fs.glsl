@@ -0,0 +1,6 @@
+// the entity id, encoded as a color by the picking pass
+uniform highp vec4 uPickingColor;
+
+void main(void) {
+    gl_FragColor = uPickingColor;
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+attribute vec4 aVertexPosition;
+
+uniform mat4 uModelViewMatrix;
+uniform mat4 uProjectionMatrix;
+uniform mat4 uTransformationMatrix;
+
+void main(void) {
+    gl_Position = uProjectionMatrix * uModelViewMatrix * uTransformationMatrix * aVertexPosition;
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use nalgebra::Vector2;
+use std::f32::consts::{PI, TAU};
+
+/// A mouse button, finger or pen that is down.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Pointer {
+    pub id: i32,
+    pub position: Vector2<f32>,
+    /// Position as of the last frame.
+    pub previous: Vector2<f32>,
+}
+
+/// How two pointers moved since the last frame, in screen space.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Gesture {
+    /// Point between the pointers at the last frame.
+    pub from: Vector2<f32>,
+    /// Point between the pointers now.
+    pub to: Vector2<f32>,
+    /// Ratio of the distances between the pointers, above 1 when they spread.
+    pub scale: f32,
+    /// Radians the line between the pointers turned by, clockwise on screen.
+    pub rotation: f32,
+}
+
+/// Pointers that are down, in the order they went down.
+#[derive(Default)]
+pub(crate) struct Pointers {
+    down: Vec<Pointer>,
+}
+
+impl Pointers {
+    pub fn press(&mut self, id: i32, position: Vector2<f32>) {
+        self.release(id);
+        self.down.push(Pointer {
+            id,
+            position,
+            previous: position,
+        });
+    }
+
+    /// Returns whether the pointer is down.
+    pub fn move_to(&mut self, id: i32, position: Vector2<f32>) -> bool {
+        match self.down.iter_mut().find(|pointer| pointer.id == id) {
+            Some(pointer) => {
+                pointer.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the pointer was down.
+    pub fn release(&mut self, id: i32) -> bool {
+        let count = self.down.len();
+        self.down.retain(|pointer| pointer.id != id);
+        self.down.len() != count
+    }
+
+    pub fn len(&self) -> usize {
+        self.down.len()
+    }
+
+    /// The pointer that went down first.
+    pub fn primary(&self) -> Option<&Pointer> {
+        self.down.first()
+    }
+
+    /// Movement of the first two pointers since the last frame.
+    pub fn gesture(&self) -> Option<Gesture> {
+        let (a, b) = match self.down.as_slice() {
+            [a, b, ..] => (a, b),
+            _ => return None,
+        };
+        let before = b.previous - a.previous;
+        let after = b.position - a.position;
+        if before.magnitude() < f32::EPSILON || after.magnitude() < f32::EPSILON {
+            return None;
+        }
+        let mut rotation = after.y.atan2(after.x) - before.y.atan2(before.x);
+        if rotation > PI {
+            rotation -= TAU;
+        } else if rotation < -PI {
+            rotation += TAU;
+        }
+        Some(Gesture {
+            from: (a.previous + b.previous) / 2.0,
+            to: (a.position + b.position) / 2.0,
+            scale: after.magnitude() / before.magnitude(),
+            rotation,
+        })
+    }
+
+    /// The frame used the current positions, the next gesture starts from them.
+    pub fn end_frame(&mut self) {
+        for pointer in &mut self.down {
+            pointer.previous = pointer.position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::setup;
+    use crate::{process_input, DragMode, MouseState};
+
+    #[test]
+    fn two_pointers_make_a_gesture() {
+        let mut pointers = Pointers::default();
+        pointers.press(1, Vector2::new(100.0, 100.0));
+        assert_eq!(pointers.gesture(), None);
+        pointers.press(7, Vector2::new(200.0, 100.0));
+
+        pointers.move_to(7, Vector2::new(150.0, 200.0));
+        let gesture = pointers.gesture().unwrap();
+
+        assert_eq!(gesture.from, Vector2::new(150.0, 100.0));
+        assert_eq!(gesture.to, Vector2::new(125.0, 150.0));
+        assert!((gesture.scale - 125.0f32.sqrt() / 10.0).abs() < 1e-5);
+        assert!((gesture.rotation - 2.0f32.atan2(1.0)).abs() < 1e-5);
+
+        pointers.end_frame();
+        assert_eq!(pointers.gesture().unwrap().scale, 1.0);
+    }
+
+    #[test]
+    fn released_pointers_are_forgotten() {
+        let mut pointers = Pointers::default();
+        pointers.press(1, Vector2::new(0.0, 0.0));
+        pointers.press(2, Vector2::new(10.0, 0.0));
+
+        assert!(pointers.release(1));
+        assert!(!pointers.release(1));
+        assert!(!pointers.move_to(1, Vector2::new(5.0, 5.0)));
+
+        assert_eq!(pointers.len(), 1);
+        assert_eq!(pointers.primary().unwrap().id, 2);
+    }
+
+    #[test]
+    fn two_finger_gesture_keeps_the_touched_positions_under_the_fingers() {
+        let (_, mut renderer) = setup(|_| {});
+        let first = renderer.camera.screen_to_world(Vector2::new(300.0, 300.0));
+        let second = renderer.camera.screen_to_world(Vector2::new(500.0, 300.0));
+        renderer.pointer_down(1, Vector2::new(300.0, 300.0), DragMode::Pan, false);
+        renderer.pointer_down(2, Vector2::new(500.0, 300.0), DragMode::Pan, false);
+        process_input(&mut renderer, 0.0);
+
+        // spread, turn and move the fingers
+        renderer.pointer_move(1, Vector2::new(250.0, 200.0));
+        renderer.pointer_move(2, Vector2::new(450.0, 450.0));
+        process_input(&mut renderer, 16.0);
+
+        let under_first = renderer.camera.screen_to_world(Vector2::new(250.0, 200.0));
+        let under_second = renderer.camera.screen_to_world(Vector2::new(450.0, 450.0));
+        assert!((under_first - first).abs().max() < 1e-3);
+        assert!((under_second - second).abs().max() < 1e-3);
+    }
+
+    #[test]
+    fn lifting_a_finger_goes_back_to_dragging() {
+        let (_, mut renderer) = setup(|_| {});
+        renderer.pointer_down(1, Vector2::new(300.0, 300.0), DragMode::Pan, false);
+        renderer.pointer_down(2, Vector2::new(500.0, 300.0), DragMode::Pan, false);
+        assert!(renderer.mouse_state == MouseState::Gesture);
+
+        assert!(renderer.pointer_up(1));
+        let grabbed = renderer.camera.screen_to_world(Vector2::new(500.0, 300.0));
+        renderer.pointer_move(2, Vector2::new(420.0, 260.0));
+        process_input(&mut renderer, 0.0);
+
+        assert!(renderer.mouse_state == MouseState::Drag);
+        let under_finger = renderer.camera.screen_to_world(Vector2::new(420.0, 260.0));
+        assert!((under_finger - grabbed).abs().max() < 1e-3);
+        assert!(renderer.pointer_up(2));
+        assert!(renderer.mouse_state == MouseState::Up);
+    }
+}
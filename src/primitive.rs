@@ -1,95 +1,143 @@
-use crate::mesh::Mesh;
-use nalgebra::{Matrix4, Vector3};
-use web_sys::{WebGlRenderingContext};
+use crate::backend::{Backend, ProgramId, UniformValue};
+use crate::camera::Ray;
 use crate::drawable::Drawable;
+use crate::error::RendererError;
 use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::picking::Bounds;
 use crate::Renderer;
+use nalgebra::{Matrix4, Vector3};
+use std::f32::consts::{PI, TAU};
 
 pub struct Primitive {
     pub position: Vector3<f32>,
     pub rotation: Vector3<f32>,
     pub scale: Vector3<f32>,
+    /// State at the start of the current simulation step, drawn frames interpolate from it.
+    previous_position: Vector3<f32>,
+    previous_rotation: Vector3<f32>,
     mesh: Mesh,
     material: Material,
 }
 
 impl Drawable for Primitive {
-    fn draw(&self, renderer: &Renderer) {
-        let gl = &renderer.gl;
-        self.bind(renderer);
-        gl.draw_elements_with_i32(
-            WebGlRenderingContext::TRIANGLES,
-            self.mesh.vertex_count,
-            WebGlRenderingContext::UNSIGNED_SHORT,
-            0,
-        );
+    fn draw(&self, renderer: &Renderer) -> Result<(), RendererError> {
+        self.bind(renderer)?;
+        self.mesh
+            .draw(renderer.backend.as_ref(), self.material.shader)
     }
 }
 
 impl Primitive {
-    fn bind(&self, renderer: &Renderer) {
-        let gl = &renderer.gl;
-        self.material.bind(renderer);
-        self.mesh.bind(gl, &self.material);
+    fn bind(&self, renderer: &Renderer) -> Result<(), RendererError> {
+        let backend = renderer.backend.as_ref();
+        self.material.bind(renderer)?;
 
-        let shader = &self.material.shader;
+        let shader = self.material.shader;
 
         // add transformation uniform
-        let transformation_matrix = Matrix4::new_translation(&self.position)
-            * Matrix4::from_euler_angles(self.rotation.x, self.rotation.y, self.rotation.z)
-            * Matrix4::new_nonuniform_scaling(&self.scale);
-
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(
-                &gl.get_uniform_location(shader, "uTransformationMatrix")
-                    .expect("can't get transformation matrix location"),
-            ),
-            false,
-            transformation_matrix.as_slice(),
-        );
+        let transformation_matrix = self.interpolated_transformation_matrix(renderer.interpolation);
+        backend.set_uniform(
+            shader,
+            "uTransformationMatrix",
+            UniformValue::Mat4(transformation_matrix),
+        )?;
 
+        // an entity flattened by a zero scale keeps its transformation for the normals
         let normal_matrix = transformation_matrix
             .try_inverse()
-            .expect("failed to inverse model view matrix for normal matrix creation")
-            .transpose();
-
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(
-                &gl.get_uniform_location(shader, "uNormalMatrix")
-                    .expect("can't get normal matrix location"),
-            ),
-            false,
-            normal_matrix.as_slice(),
+            .map(|inverse| inverse.transpose())
+            .unwrap_or(transformation_matrix);
+        backend.set_uniform(shader, "uNormalMatrix", UniformValue::Mat4(normal_matrix))
+    }
+
+    /// Draw the mesh alone with `program`, whose other uniforms are already set.
+    pub fn draw_with(
+        &self,
+        backend: &dyn Backend,
+        program: ProgramId,
+        alpha: f32,
+    ) -> Result<(), RendererError> {
+        backend.set_uniform(
+            program,
+            "uTransformationMatrix",
+            UniformValue::Mat4(self.interpolated_transformation_matrix(alpha)),
+        )?;
+        self.mesh.draw(backend, program)
+    }
+
+    /// Transformation between the previous step (`alpha` = 0) and the current one (1).
+    pub fn interpolated_transformation_matrix(&self, alpha: f32) -> Matrix4<f32> {
+        let position = self.previous_position.lerp(&self.position, alpha);
+        let rotation = self
+            .previous_rotation
+            .zip_map(&self.rotation, |from, to| lerp_angle(from, to, alpha));
+        Matrix4::new_translation(&position)
+            * Matrix4::from_euler_angles(rotation.x, rotation.y, rotation.z)
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// Distance along the world space `ray` to the entity as drawn with `alpha`, and the hit
+    /// position in its mesh coordinates.
+    pub fn intersect(&self, ray: &Ray, alpha: f32) -> Option<(f32, Vector3<f32>)> {
+        let local = ray.transform(
+            &self
+                .interpolated_transformation_matrix(alpha)
+                .try_inverse()?,
         );
+        let distance = self.mesh.intersect(&local)?;
+        Some((distance, local.origin + local.direction * distance))
     }
 
-    pub fn new_quad(gl: &WebGlRenderingContext, material: Material) -> Primitive {
-        Primitive {
-            position: Vector3::new(0.0, 0.0, 0.0),
-            rotation: Vector3::new(0.0, 0.0, 0.0),
-            scale: Vector3::new(1.0, 1.0, 1.0),
-            mesh: Mesh::quad(gl),
-            material,
-        }
+    /// Box around the entity as drawn with `alpha`, in world space.
+    pub fn world_bounds(&self, alpha: f32) -> Bounds {
+        self.mesh
+            .bounds()
+            .transform(&self.interpolated_transformation_matrix(alpha))
     }
 
-    pub fn new_cube(gl: &WebGlRenderingContext, material: Material) -> Primitive {
-        Primitive {
-            position: Vector3::new(0.0, 0.0, 0.0),
-            rotation: Vector3::new(0.0, 0.0, 0.0),
-            scale: Vector3::new(1.0, 1.0, 1.0),
-            mesh: Mesh::cube(gl),
-            material
-        }
+    /// Put the entity at `position` right away, drawn frames do not interpolate to it.
+    pub fn move_to(&mut self, position: Vector3<f32>) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    /// Start a new simulation step from the current state.
+    pub fn snapshot(&mut self) {
+        self.previous_position = self.position;
+        self.previous_rotation = self.rotation;
+    }
+
+    /// Draw `mesh` from now on, `mesh` gets the one drawn until now.
+    pub fn swap_mesh(&mut self, mesh: &mut Mesh) {
+        std::mem::swap(&mut self.mesh, mesh);
+    }
+
+    /// Release the mesh only, the material is shared with the rest of the scene.
+    pub fn delete_mesh(&self, backend: &dyn Backend) {
+        self.mesh.delete(backend);
     }
 
-    pub fn new_text(gl: &WebGlRenderingContext, material: Material, input_text: &str) -> Primitive {
+    pub fn new(mesh: Mesh, material: Material) -> Primitive {
         Primitive {
-            position: Vector3::new(0.0, 0.0, 0.0),
-            rotation: Vector3::new(0.0, 0.0, 0.0),
+            position: Vector3::zeros(),
+            rotation: Vector3::zeros(),
             scale: Vector3::new(1.0, 1.0, 1.0),
-            mesh: Mesh::text(gl, input_text),
-            material
+            previous_position: Vector3::zeros(),
+            previous_rotation: Vector3::zeros(),
+            mesh,
+            material,
         }
     }
 }
+
+/// Interpolate along the shortest way around, angles are kept within one turn.
+pub(crate) fn lerp_angle(from: f32, to: f32, alpha: f32) -> f32 {
+    let mut delta = (to - from) % TAU;
+    if delta > PI {
+        delta -= TAU;
+    } else if delta < -PI {
+        delta += TAU;
+    }
+    from + delta * alpha
+}
@@ -0,0 +1,172 @@
+use crate::camera::WorldRect;
+use crate::picking::Bounds;
+use nalgebra::Vector3;
+
+/// Selected entities by index in the scene, in the order they were selected.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub(crate) struct Selection {
+    entities: Vec<usize>,
+}
+
+impl Selection {
+    pub fn entities(&self) -> &[usize] {
+        &self.entities
+    }
+
+    pub fn contains(&self, entity: usize) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    /// The entity selected last, keyboard selection moves on from it.
+    pub fn primary(&self) -> Option<usize> {
+        self.entities.last().copied()
+    }
+
+    /// Select `entity` alone, or nothing.
+    pub fn replace(&mut self, entity: Option<usize>) {
+        self.entities.clear();
+        self.entities.extend(entity);
+    }
+
+    pub fn add(&mut self, entity: usize) {
+        if !self.contains(entity) {
+            self.entities.push(entity);
+        }
+    }
+
+    pub fn toggle(&mut self, entity: usize) {
+        if self.contains(entity) {
+            self.entities.retain(|&selected| selected != entity);
+        } else {
+            self.entities.push(entity);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
+
+/// Rubber band dragged on the z = 0 plane, the entities it touches join the selection.
+pub(crate) struct BoxSelect {
+    pub from: Vector3<f32>,
+    pub to: Vector3<f32>,
+    /// Selection when the drag started, the band adds to it.
+    pub base: Selection,
+}
+
+impl BoxSelect {
+    pub fn rect(&self) -> WorldRect {
+        WorldRect::new(
+            self.from.x.min(self.to.x),
+            self.from.y.min(self.to.y),
+            self.from.x.max(self.to.x),
+            self.from.y.max(self.to.y),
+        )
+    }
+
+    /// Whether the band touches an entity with the given world bounds, seen from above.
+    pub fn touches(&self, bounds: &Bounds) -> bool {
+        let rect = self.rect();
+        bounds.min.x <= rect.max_x
+            && bounds.max.x >= rect.min_x
+            && bounds.min.y <= rect.max_y
+            && bounds.max.y >= rect.min_y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scene;
+    use crate::{process_input, DragMode, Renderer};
+    use nalgebra::Vector2;
+
+    #[test]
+    fn toggling_keeps_the_selection_order() {
+        let mut selection = Selection::default();
+        selection.replace(Some(4));
+        selection.toggle(1);
+        selection.toggle(7);
+        selection.toggle(4);
+        assert_eq!(selection.entities(), &[1, 7]);
+        assert_eq!(selection.primary(), Some(7));
+
+        selection.add(1);
+        selection.add(3);
+        assert_eq!(selection.entities(), &[1, 7, 3]);
+    }
+
+    #[test]
+    fn band_touches_bounds_it_overlaps() {
+        let band = BoxSelect {
+            from: Vector3::new(2.0, 1.0, 0.0),
+            to: Vector3::new(-1.0, -1.0, 0.0),
+            base: Default::default(),
+        };
+        let bounds = |min_x, max_x| Bounds {
+            min: Vector3::new(min_x, 0.0, -1.0),
+            max: Vector3::new(max_x, 0.5, 1.0),
+        };
+
+        assert!(band.touches(&bounds(1.5, 3.0)));
+        assert!(band.touches(&bounds(-5.0, 5.0)));
+        assert!(!band.touches(&bounds(2.5, 3.0)));
+    }
+
+    #[test]
+    fn clicks_replace_the_selection_and_toggle_with_a_modifier() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(-3.0, 0.0, 0.0);
+            options.add_quad(3.0, 0.0, 0.0);
+        });
+        let left = renderer
+            .camera
+            .world_to_screen(Vector3::new(-3.0, 0.0, 0.0));
+        let right = renderer.camera.world_to_screen(Vector3::new(3.0, 0.0, 0.0));
+        let click = |renderer: &mut Renderer, position, toggle| {
+            renderer.pointer_down(1, position, DragMode::Pan, toggle);
+            renderer.pointer_up(1);
+        };
+
+        click(&mut renderer, left, false);
+        click(&mut renderer, right, true);
+        assert_eq!(renderer.selection.entities(), &[0, 1]);
+        assert_eq!(renderer.take_selection_change(), Some(vec![0, 1]));
+        assert_eq!(renderer.take_selection_change(), None);
+
+        click(&mut renderer, left, true);
+        assert_eq!(renderer.selection.entities(), &[1]);
+        click(&mut renderer, Vector2::new(400.0, 300.0), true);
+        assert_eq!(renderer.selection.entities(), &[1]);
+        click(&mut renderer, Vector2::new(400.0, 300.0), false);
+        assert_eq!(renderer.selection.entities(), &[] as &[usize]);
+    }
+
+    #[test]
+    fn modifier_drag_from_empty_space_draws_a_rubber_band() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(-3.0, 0.0, 0.0);
+            options.add_quad(3.0, 0.0, 0.0);
+            options.add_cube(0.0, 3.0, 0.0);
+        });
+        let camera = renderer.camera.clone();
+        let screen = |x, y| camera.world_to_screen(Vector3::new(x, y, 0.0));
+        renderer.select(&[2]);
+
+        renderer.pointer_down(1, screen(-5.0, -2.0), DragMode::Orbit, true);
+        renderer.pointer_move(1, screen(-4.0, -1.5));
+        process_input(&mut renderer, 0.0);
+        renderer.pointer_move(1, screen(-2.5, 1.0));
+        process_input(&mut renderer, 16.0);
+
+        assert_eq!(renderer.selection.entities(), &[2, 0]);
+        assert!(renderer.selection_box_corners().is_some());
+        assert_eq!(renderer.camera, camera);
+
+        renderer.pointer_up(1);
+        process_input(&mut renderer, 32.0);
+        assert!(renderer.selection_box_corners().is_none());
+        assert_eq!(renderer.selection.entities(), &[2, 0]);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::backend::FrameCounters;
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
+/// Time between the last [`FrameTimes::CAPACITY`] consecutive animation frames.
+#[derive(Default)]
+pub(crate) struct FrameTimes {
+    samples: VecDeque<f64>,
+    last_timestamp: Option<f64>,
+}
+
+impl FrameTimes {
+    const CAPACITY: usize = 120;
+
+    /// Account for an animation frame that started at `timestamp` milliseconds.
+    pub fn record(&mut self, timestamp: f64) {
+        if let Some(last) = self.last_timestamp.replace(timestamp) {
+            if self.samples.len() == Self::CAPACITY {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(timestamp - last);
+        }
+    }
+
+    /// The update loop stops, the time until it starts again is not a frame time.
+    pub fn pause(&mut self) {
+        self.last_timestamp = None;
+    }
+}
+
+/// Statistics of the recently drawn frames, times are in milliseconds.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct FrameStats {
+    frame_time_min: f64,
+    frame_time_avg: f64,
+    frame_time_p95: f64,
+    frame_time_max: f64,
+    counters: FrameCounters,
+}
+
+impl FrameStats {
+    /// `counters` are the ones of the last drawn frame.
+    pub(crate) fn new(frame_times: &FrameTimes, counters: FrameCounters) -> FrameStats {
+        let mut samples: Vec<f64> = frame_times.samples.iter().copied().collect();
+        if samples.is_empty() {
+            return FrameStats {
+                counters,
+                ..Default::default()
+            };
+        }
+        samples.sort_by(|a, b| a.total_cmp(b));
+        let p95 = ((samples.len() as f64 * 0.95).ceil() as usize).clamp(1, samples.len()) - 1;
+        FrameStats {
+            frame_time_min: samples[0],
+            frame_time_avg: samples.iter().sum::<f64>() / samples.len() as f64,
+            frame_time_p95: samples[p95],
+            frame_time_max: samples[samples.len() - 1],
+            counters,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl FrameStats {
+    #[wasm_bindgen(getter)]
+    pub fn frame_time_min(&self) -> f64 {
+        self.frame_time_min
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frame_time_avg(&self) -> f64 {
+        self.frame_time_avg
+    }
+
+    /// 95% of the recent frames took less time than this.
+    #[wasm_bindgen(getter)]
+    pub fn frame_time_p95(&self) -> f64 {
+        self.frame_time_p95
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frame_time_max(&self) -> f64 {
+        self.frame_time_max
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn draw_calls(&self) -> u32 {
+        self.counters.draw_calls
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn triangles(&self) -> u32 {
+        self.counters.triangles
+    }
+
+    /// Program, texture and vertex array binds of the last frame.
+    #[wasm_bindgen(getter)]
+    pub fn state_changes(&self) -> u32 {
+        self.counters.state_changes
+    }
+
+    /// Bytes used by the textures.
+    #[wasm_bindgen(getter)]
+    pub fn texture_memory(&self) -> f64 {
+        self.counters.texture_memory as f64
+    }
+
+    /// `undefined` unless the browser exposes `EXT_disjoint_timer_query`.
+    #[wasm_bindgen(getter)]
+    pub fn gpu_time(&self) -> Option<f64> {
+        self.counters.gpu_time
+    }
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "frame {:.1} ms (min {:.1}, p95 {:.1}, max {:.1})",
+            self.frame_time_avg, self.frame_time_min, self.frame_time_p95, self.frame_time_max
+        )?;
+        if let Some(gpu_time) = self.counters.gpu_time {
+            writeln!(f, "gpu {:.2} ms", gpu_time)?;
+        }
+        writeln!(
+            f,
+            "{} draw calls, {} triangles, {} state changes",
+            self.counters.draw_calls, self.counters.triangles, self.counters.state_changes
+        )?;
+        write!(
+            f,
+            "textures {:.1} MiB",
+            self.counters.texture_memory as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::present;
+    use crate::tests::scene;
+
+    fn frame_times(durations: &[f64]) -> FrameTimes {
+        let mut frame_times = FrameTimes::default();
+        let mut timestamp = 0.0;
+        frame_times.record(timestamp);
+        for duration in durations {
+            timestamp += duration;
+            frame_times.record(timestamp);
+        }
+        frame_times
+    }
+
+    #[test]
+    fn summarizes_frame_times() {
+        let mut durations = vec![10.0; 19];
+        durations.push(50.0);
+        let stats = FrameStats::new(&frame_times(&durations), Default::default());
+
+        assert_eq!(stats.frame_time_min(), 10.0);
+        assert_eq!(stats.frame_time_avg(), 12.0);
+        assert_eq!(stats.frame_time_p95(), 10.0);
+        assert_eq!(stats.frame_time_max(), 50.0);
+    }
+
+    #[test]
+    fn keeps_only_recent_frames() {
+        let mut durations = vec![100.0];
+        durations.extend(vec![16.0; FrameTimes::CAPACITY]);
+        let stats = FrameStats::new(&frame_times(&durations), Default::default());
+        assert_eq!(stats.frame_time_max(), 16.0);
+    }
+
+    #[test]
+    fn pauses_are_not_frames() {
+        let mut frame_times = frame_times(&[16.0]);
+        frame_times.pause();
+        frame_times.record(5000.0);
+        frame_times.record(5016.0);
+
+        let stats = FrameStats::new(&frame_times, Default::default());
+        assert_eq!(stats.frame_time_max(), 16.0);
+    }
+
+    #[test]
+    fn stats_count_the_last_drawn_frame() {
+        let (_, mut renderer) = scene(|options| {
+            options.add_quad(0.0, 0.0, 0.0);
+            options.add_cube(1.0, 0.0, 0.0);
+        });
+
+        present(&mut renderer);
+        present(&mut renderer);
+
+        let stats = renderer.stats;
+        assert_eq!(stats.draw_calls(), 2);
+        assert_eq!(stats.triangles(), 2 + 12);
+        // program, texture and vertex array of each entity
+        assert_eq!(stats.state_changes(), 6);
+        assert_eq!(stats.gpu_time(), None);
+    }
+}
@@ -0,0 +1,132 @@
+/// Splits the variable time between animation frames into fixed simulation steps.
+///
+/// The simulation always advances by [`FixedTimestep::step`], whatever the display rate, and
+/// the time left over is exposed through [`FixedTimestep::alpha`] to interpolate what is drawn
+/// between the last two steps.
+pub(crate) struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    last_timestamp: Option<f64>,
+}
+
+impl FixedTimestep {
+    /// Longer frames, like the first one after the loop was paused, are not caught up.
+    const MAX_FRAME_TIME: f64 = 0.25;
+
+    /// `tick_rate` is the number of steps per second.
+    pub fn new(tick_rate: f64) -> FixedTimestep {
+        FixedTimestep {
+            step: 1.0 / tick_rate,
+            accumulator: 0.0,
+            last_timestamp: None,
+        }
+    }
+
+    /// Duration of one step in seconds.
+    pub fn step(&self) -> f32 {
+        self.step as f32
+    }
+
+    /// Account for a frame drawn at `timestamp` milliseconds, returns how many steps to run.
+    pub fn advance(&mut self, timestamp: f64) -> u32 {
+        let elapsed = match self.last_timestamp {
+            Some(last) => ((timestamp - last) * 0.001).clamp(0.0, Self::MAX_FRAME_TIME),
+            None => 0.0,
+        };
+        self.last_timestamp = Some(timestamp);
+        self.accumulator += elapsed;
+        // tolerate the rounding of frame durations that are exact multiples of the step
+        let steps = ((self.accumulator + 1e-9) / self.step).floor();
+        self.accumulator = (self.accumulator - steps * self.step).max(0.0);
+        steps as u32
+    }
+
+    /// Position of the current frame between the last step and the next one, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).min(1.0) as f32
+    }
+
+    /// Forget the last frame, the next one starts a new run without catching up the pause.
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+        self.last_timestamp = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RecordingBackend;
+    use crate::tests::{renderer, scene};
+    use crate::{tick, RendererOptions};
+    use nalgebra::{Matrix4, Vector3};
+
+    fn steps_over_one_second(display_rate: f64) -> u32 {
+        let mut timestep = FixedTimestep::new(60.0);
+        (0..=display_rate as u32)
+            .map(|frame| timestep.advance(frame as f64 * 1000.0 / display_rate))
+            .sum()
+    }
+
+    #[test]
+    fn step_count_does_not_depend_on_the_display_rate() {
+        assert_eq!(steps_over_one_second(30.0), 60);
+        assert_eq!(steps_over_one_second(60.0), 60);
+        assert_eq!(steps_over_one_second(144.0), 60);
+    }
+
+    #[test]
+    fn alpha_is_the_time_left_over() {
+        let mut timestep = FixedTimestep::new(10.0);
+        timestep.advance(0.0);
+        assert_eq!(timestep.advance(150.0), 1);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_pauses_are_not_caught_up() {
+        let mut timestep = FixedTimestep::new(60.0);
+        timestep.advance(0.0);
+        assert_eq!(timestep.advance(10_000.0), 15);
+
+        timestep.reset();
+        assert_eq!(timestep.advance(20_000.0), 0);
+    }
+
+    #[test]
+    fn ticks_are_deterministic() {
+        let mut options = RendererOptions::default();
+        options.clear_scene();
+        options.add_cube(2.0, 0.0, 0.0);
+        let backend = RecordingBackend::new();
+        let mut first = renderer(&backend, &options);
+        let mut second = renderer(&backend, &options);
+
+        for _ in 0..120 {
+            tick(&mut first, 1.0 / 60.0);
+        }
+        for _ in 0..60 {
+            tick(&mut second, 1.0 / 60.0);
+        }
+        let halfway = second.entities[0].rotation;
+        for _ in 0..60 {
+            tick(&mut second, 1.0 / 60.0);
+        }
+
+        assert!(halfway.z > 0.0);
+        assert_eq!(first.entities[0].rotation, second.entities[0].rotation);
+    }
+
+    #[test]
+    fn drawn_frames_interpolate_between_steps() {
+        let (_, mut renderer) = scene(|options| options.add_cube(2.0, 0.0, 0.0));
+        tick(&mut renderer, 1.0);
+        let entity = &renderer.entities[0];
+
+        let halfway = entity.interpolated_transformation_matrix(0.5);
+
+        let expected = Matrix4::new_translation(&Vector3::new(2.0, 0.0, 0.0))
+            * Matrix4::from_euler_angles(0.0, 0.0, 0.1);
+        assert!((halfway - expected).abs().max() < 1e-6);
+    }
+}
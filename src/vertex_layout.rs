@@ -0,0 +1,202 @@
+use crate::backend::{AttributeType, BufferId, VertexAttribute};
+use nalgebra::Vector3;
+
+/// One attribute of a [`VertexLayout`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct AttributeLayout {
+    /// Name of the attribute in the shaders, like `aVertexPosition`.
+    pub name: String,
+    pub components: i32,
+    pub kind: AttributeType,
+    pub normalized: bool,
+    /// Bytes from the start of a vertex to the first component.
+    pub offset: i32,
+}
+
+impl AttributeLayout {
+    /// Bytes taken by the value of one vertex.
+    pub fn size(&self) -> usize {
+        (self.components * self.kind.size()) as usize
+    }
+}
+
+/// How the attributes of one vertex are laid out in a vertex buffer.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct VertexLayout {
+    attributes: Vec<AttributeLayout>,
+    /// Bytes taken by one vertex.
+    stride: i32,
+}
+
+impl VertexLayout {
+    pub fn new() -> VertexLayout {
+        Default::default()
+    }
+
+    pub fn attributes(&self) -> &[AttributeLayout] {
+        &self.attributes
+    }
+
+    /// Append an attribute after the previous ones. Offsets are kept on 4 bytes, as WebGL
+    /// requires for every attribute type.
+    pub fn attribute(
+        mut self,
+        name: &str,
+        components: i32,
+        kind: AttributeType,
+        normalized: bool,
+    ) -> VertexLayout {
+        let offset = self.stride;
+        self.stride = align(offset + components * kind.size());
+        self.attributes.push(AttributeLayout {
+            name: name.to_string(),
+            components,
+            kind,
+            normalized,
+            offset,
+        });
+        self
+    }
+
+    /// Vertex buffer holding `values`, which has the values of each attribute in order, packed
+    /// one vertex after the other.
+    pub fn interleave(&self, values: &[&[u8]]) -> Vec<u8> {
+        let stride = self.stride as usize;
+        let vertex_count = match (self.attributes.first(), values.first()) {
+            (Some(attribute), Some(values)) => values.len() / attribute.size(),
+            _ => 0,
+        };
+        let mut buffer = vec![0; vertex_count * stride];
+        for (attribute, values) in self.attributes.iter().zip(values) {
+            let size = attribute.size();
+            for (vertex, value) in values.chunks_exact(size).enumerate() {
+                let start = vertex * stride + attribute.offset as usize;
+                buffer[start..start + size].copy_from_slice(value);
+            }
+        }
+        buffer
+    }
+
+    /// The attributes as read from `buffer` by a vertex array.
+    pub fn vertex_attributes(
+        &self,
+        buffer: BufferId,
+    ) -> impl Iterator<Item = VertexAttribute> + '_ {
+        self.attributes
+            .iter()
+            .map(move |attribute| VertexAttribute {
+                components: attribute.components,
+                kind: attribute.kind,
+                normalized: attribute.normalized,
+                stride: self.stride,
+                offset: attribute.offset,
+                ..VertexAttribute::new(&attribute.name, buffer, attribute.components)
+            })
+    }
+}
+
+/// Names of the attributes the default shader reads, every
+/// [`crate::mesh_data::MeshBuilder`] gives them to its
+/// vertices.
+pub const POSITION: &str = "aVertexPosition";
+pub const TEXTURE_COORD: &str = "aTextureCoord";
+pub const NORMAL: &str = "aVertexNormal";
+
+/// Values of one vertex attribute, one vertex after the other.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AttributeStream {
+    /// Layout of the attribute alone in a vertex buffer.
+    pub layout: VertexLayout,
+    /// Little-endian components of each vertex, packed without the padding of the layout.
+    pub data: Vec<u8>,
+}
+
+impl AttributeStream {
+    pub fn new(
+        name: &str,
+        components: i32,
+        kind: AttributeType,
+        normalized: bool,
+    ) -> AttributeStream {
+        AttributeStream {
+            layout: VertexLayout::new().attribute(name, components, kind, normalized),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn attribute(&self) -> &AttributeLayout {
+        &self.layout.attributes()[0]
+    }
+
+    pub fn name(&self) -> &str {
+        &self.attribute().name
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.data.len() / self.attribute().size()
+    }
+
+    /// Bytes of the value of `vertex`.
+    pub fn value(&self, vertex: usize) -> &[u8] {
+        let size = self.attribute().size();
+        &self.data[vertex * size..(vertex + 1) * size]
+    }
+
+    /// Value of `vertex` in a float attribute of three components.
+    pub fn vector3(&self, vertex: usize) -> Vector3<f32> {
+        let value = self.value(vertex);
+        Vector3::from_fn(|i, _| {
+            f32::from_le_bytes([
+                value[i * 4],
+                value[i * 4 + 1],
+                value[i * 4 + 2],
+                value[i * 4 + 3],
+            ])
+        })
+    }
+
+    pub(crate) fn push_zeros(&mut self, vertex_count: usize) {
+        let size = self.attribute().size();
+        self.data.resize(self.data.len() + vertex_count * size, 0);
+    }
+}
+
+fn align(bytes: i32) -> i32 {
+    (bytes + 3) / 4 * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_follow_each_other_on_four_bytes() {
+        let layout = VertexLayout::new()
+            .attribute("aVertexPosition", 3, AttributeType::Float, false)
+            .attribute("aVertexColor", 3, AttributeType::UnsignedByte, true)
+            .attribute("aEntityId", 1, AttributeType::UnsignedShort, false);
+
+        let attributes: Vec<_> = layout.vertex_attributes(BufferId(7)).collect();
+        let offsets: Vec<_> = attributes
+            .iter()
+            .map(|attribute| (attribute.stride, attribute.offset))
+            .collect();
+        assert_eq!(offsets, vec![(20, 0), (20, 12), (20, 16)]);
+        assert_eq!(attributes[1].kind, AttributeType::UnsignedByte);
+        assert!(attributes[1].normalized);
+        assert_eq!(attributes[2].buffer, BufferId(7));
+    }
+
+    #[test]
+    fn interleaved_values_are_padded_to_the_stride() {
+        let layout = VertexLayout::new()
+            .attribute("aVertexColor", 3, AttributeType::UnsignedByte, true)
+            .attribute("aEntityId", 1, AttributeType::UnsignedShort, false);
+
+        let colors = [1, 2, 3, 4, 5, 6];
+        let ids = [7, 0, 8, 0];
+        let buffer = layout.interleave(&[&colors, &ids]);
+
+        assert_eq!(buffer, vec![1, 2, 3, 0, 7, 0, 0, 0, 4, 5, 6, 0, 8, 0, 0, 0]);
+    }
+}
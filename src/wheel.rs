@@ -0,0 +1,166 @@
+use nalgebra::Vector2;
+use web_sys::WheelEvent;
+
+/// How strongly wheel events move the camera, both are multipliers of the default speed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct WheelSettings {
+    pub zoom_sensitivity: f32,
+    pub pan_sensitivity: f32,
+}
+
+/// One wheel event, as reported by the browser.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct WheelDelta {
+    pub x: f64,
+    pub y: f64,
+    /// One of the `WheelEvent::DOM_DELTA_*` constants.
+    pub mode: u32,
+    pub ctrl_key: bool,
+}
+
+/// Wheel input received since the last frame.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub(crate) struct WheelInput {
+    /// Logarithm of the zoom factor to ease to, from mouse wheels.
+    pub zoom: f32,
+    /// Logarithm of the zoom factor to apply right away, from trackpad pinches.
+    pub pinch: f32,
+    /// Pixels to scroll the view by, from two finger trackpad scrolls.
+    pub pan: Vector2<f32>,
+}
+
+impl WheelInput {
+    /// Pixels of a line when deltas are reported in lines, as Firefox does for mouse wheels.
+    const LINE_HEIGHT: f64 = 16.0;
+    /// Zoom logarithm per pixel of wheel delta, a 100 pixel mouse notch zooms by about 10%.
+    const WHEEL_ZOOM_SPEED: f32 = 0.001;
+    /// Browsers report pinches with much smaller deltas than wheel notches.
+    const PINCH_ZOOM_SPEED: f32 = 0.01;
+    /// Largest zoom logarithm of a single event, page deltas and fast flicks stay usable.
+    const MAX_ZOOM_STEP: f32 = 0.5;
+
+    /// Account for one event, `page_height` is the height in pixels of a page scroll.
+    ///
+    /// Browsers report trackpad pinches as wheel events with the ctrl key down. Trackpad
+    /// scrolls are told apart from mouse wheels by their small or horizontal pixel deltas;
+    /// mouse wheels report lines or large steps, fractional on scaled displays.
+    pub fn add(&mut self, delta: WheelDelta, page_height: f64, settings: &WheelSettings) {
+        let scale = match delta.mode {
+            WheelEvent::DOM_DELTA_LINE => Self::LINE_HEIGHT,
+            WheelEvent::DOM_DELTA_PAGE => page_height,
+            _ => 1.0,
+        };
+        let (x, y) = ((delta.x * scale) as f32, (delta.y * scale) as f32);
+
+        if delta.ctrl_key {
+            self.pinch += zoom_step(y * Self::PINCH_ZOOM_SPEED * settings.zoom_sensitivity);
+        } else if is_trackpad_scroll(&delta) {
+            self.pan += Vector2::new(x, y) * settings.pan_sensitivity;
+        } else {
+            self.zoom += zoom_step(y * Self::WHEEL_ZOOM_SPEED * settings.zoom_sensitivity);
+        }
+    }
+}
+
+fn zoom_step(step: f32) -> f32 {
+    step.clamp(-WheelInput::MAX_ZOOM_STEP, WheelInput::MAX_ZOOM_STEP)
+}
+
+fn is_trackpad_scroll(delta: &WheelDelta) -> bool {
+    delta.mode == WheelEvent::DOM_DELTA_PIXEL && (delta.x != 0.0 || delta.y.abs() < 50.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_input;
+    use crate::tests::setup;
+    use nalgebra::Vector3;
+
+    const SETTINGS: WheelSettings = WheelSettings {
+        zoom_sensitivity: 1.0,
+        pan_sensitivity: 1.0,
+    };
+
+    fn input(deltas: &[WheelDelta]) -> WheelInput {
+        let mut input = WheelInput::default();
+        for delta in deltas {
+            input.add(*delta, 600.0, &SETTINGS);
+        }
+        input
+    }
+
+    fn delta(x: f64, y: f64, mode: u32, ctrl_key: bool) -> WheelDelta {
+        WheelDelta {
+            x,
+            y,
+            mode,
+            ctrl_key,
+        }
+    }
+
+    #[test]
+    fn mouse_notches_zoom_by_the_same_amount_in_every_mode() {
+        let pixels = input(&[delta(0.0, 100.0, WheelEvent::DOM_DELTA_PIXEL, false)]);
+        let lines = input(&[delta(0.0, 6.25, WheelEvent::DOM_DELTA_LINE, false)]);
+
+        assert!((pixels.zoom - 0.1).abs() < 1e-6);
+        assert!((lines.zoom - pixels.zoom).abs() < 1e-6);
+        assert_eq!(pixels.pan, Vector2::zeros());
+    }
+
+    #[test]
+    fn fractional_mouse_notches_of_scaled_displays_zoom() {
+        for y in [66.666_664, -53.333_332, 100.0 / 1.25] {
+            let notch = input(&[delta(0.0, y, WheelEvent::DOM_DELTA_PIXEL, false)]);
+
+            assert!((notch.zoom - y as f32 * WheelInput::WHEEL_ZOOM_SPEED).abs() < 1e-6);
+            assert_eq!(notch.pan, Vector2::zeros());
+        }
+    }
+
+    #[test]
+    fn zoom_follows_the_delta_magnitude() {
+        let small = input(&[delta(0.0, -100.0, WheelEvent::DOM_DELTA_PIXEL, false)]);
+        let large = input(&[delta(0.0, -300.0, WheelEvent::DOM_DELTA_PIXEL, false)]);
+        let page = input(&[delta(0.0, 3.0, WheelEvent::DOM_DELTA_PAGE, false)]);
+
+        assert!((large.zoom - 3.0 * small.zoom).abs() < 1e-6);
+        assert_eq!(page.zoom, WheelInput::MAX_ZOOM_STEP);
+    }
+
+    #[test]
+    fn ctrl_wheel_is_a_pinch() {
+        let pinch = input(&[
+            delta(0.0, -2.5, WheelEvent::DOM_DELTA_PIXEL, true),
+            delta(0.0, -3.5, WheelEvent::DOM_DELTA_PIXEL, true),
+        ]);
+
+        assert!((pinch.pinch + 0.06).abs() < 1e-6);
+        assert_eq!(pinch.zoom, 0.0);
+    }
+
+    #[test]
+    fn trackpad_scrolls_pan() {
+        let scroll = input(&[
+            delta(4.0, 0.0, WheelEvent::DOM_DELTA_PIXEL, false),
+            delta(1.0, -2.5, WheelEvent::DOM_DELTA_PIXEL, false),
+        ]);
+
+        assert_eq!(scroll.pan, Vector2::new(5.0, -2.5));
+        assert_eq!(scroll.zoom, 0.0);
+    }
+
+    #[test]
+    fn trackpad_scroll_pans_by_the_scrolled_pixels() {
+        let (_, mut renderer) = setup(|_| {});
+        let before = renderer.camera.world_to_screen(Vector3::zeros());
+        renderer.wheel_input.pan = Vector2::new(30.0, -20.0);
+
+        process_input(&mut renderer, 0.0);
+
+        let after = renderer.camera.world_to_screen(Vector3::zeros());
+        assert!((before - after - Vector2::new(30.0, -20.0)).abs().max() < 1e-3);
+        assert_eq!(renderer.wheel_input, Default::default());
+    }
+}
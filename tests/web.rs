@@ -3,11 +3,86 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use gloo::events::EventListener;
+use js_sys::{Array, Function, Promise};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
 use wasm_bindgen_test::*;
+use web_sys::{HtmlCanvasElement, WebGlRenderingContext, WebglLoseContext};
+use work_tree::{run, RendererOptions};
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+const PIXEL_PNG: &str = "data:image/png;base64,\
+    iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNkYAAAAAYAAjCB0C8AAAAASUVORK5CYII=";
+
 #[wasm_bindgen_test]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+fn create_canvas() -> HtmlCanvasElement {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .create_element("canvas")
+        .unwrap()
+        .unchecked_into()
+}
+
+/// Resolves the first time `name` is dispatched on `canvas`.
+fn next_event(canvas: &HtmlCanvasElement, name: &'static str) -> JsFuture {
+    JsFuture::from(Promise::new(&mut |resolve, _reject| {
+        EventListener::once(canvas, name, move |_| {
+            resolve.call0(&JsValue::NULL).unwrap();
+        })
+        .forget();
+    }))
+}
+
+fn lose_context_extension(canvas: &HtmlCanvasElement) -> WebglLoseContext {
+    // getContext hands back the context the renderer already created
+    let context = canvas
+        .get_context("webgl2")
+        .unwrap()
+        .or_else(|| canvas.get_context("webgl").unwrap())
+        .unwrap();
+    // both context types share getExtension
+    context
+        .unchecked_ref::<WebGlRenderingContext>()
+        .get_extension("WEBGL_lose_context")
+        .unwrap()
+        .unwrap()
+        .unchecked_into()
+}
+
+#[wasm_bindgen_test]
+async fn scene_comes_back_after_context_loss() {
+    let canvas = create_canvas();
+    let errors = Array::new();
+    let on_error = Closure::<dyn Fn(JsValue)>::new({
+        let errors = errors.clone();
+        move |error: JsValue| {
+            errors.push(&error);
+        }
+    });
+    let mut options = RendererOptions::default();
+    options.set_canvas(canvas.clone());
+    options.set_texture_url(PIXEL_PNG.to_string());
+    options.set_on_error(on_error.as_ref().unchecked_ref::<Function>().clone());
+    let handle = run(Some(options)).unwrap();
+    let extension = lose_context_extension(&canvas);
+
+    let lost = next_event(&canvas, "webglcontextlost");
+    extension.lose_context();
+    lost.await.unwrap();
+    assert!(handle.context_lost());
+
+    let restored = next_event(&canvas, "webglcontextrestored");
+    extension.restore_context();
+    restored.await.unwrap();
+    assert!(!handle.context_lost());
+    assert_eq!(errors.length(), 0);
+}